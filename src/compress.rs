@@ -0,0 +1,349 @@
+//! Compressed input detection and compressed output.
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+use flate2::write::GzEncoder;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use zip::CompressionMethod;
+
+/// Compression format of an input, recognized by its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    /// A zip archive; its playlists are listed with [`zip_playlists`].
+    Zip,
+}
+
+impl Compression {
+    /// Recognizes the format from the magic bytes at the start of `bytes`.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Compression::Zip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Wraps `reader` in a decoder if it starts with gzip, xz or zstd data,
+/// reading the content as it is consumed. Zip archives are rejected, since
+/// their members have to be read one by one.
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(
+            reader,
+        ))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        Compression::Zip => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "zip archives cannot be read as a single playlist",
+            ))
+        }
+    })
+}
+
+/// Names of the `.m3u` and `.m3u8` members of a zip archive, in archive
+/// order.
+pub fn zip_playlists<R: Read + Seek>(reader: R) -> io::Result<Vec<String>> {
+    let archive = zip::ZipArchive::new(reader).map_err(io::Error::other)?;
+    let mut names = Vec::new();
+    for name in archive.file_names() {
+        let name = name.map_err(io::Error::other)?;
+        if is_playlist_name(&name) {
+            names.push(name.into_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// Opens the member `name` of a zip archive, decompressing it as it is
+/// read. Only stored and deflated members can be read, which is what zip
+/// tools write unless told otherwise. Reading fails at the end of a member
+/// whose content does not match its CRC-32.
+pub fn open_zip_member<'a, R: Read + Seek + 'a>(
+    reader: R,
+    name: &str,
+) -> io::Result<Box<dyn Read + 'a>> {
+    let unsupported = |what: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("cannot read zip member {:?}: {}", name, what),
+        )
+    };
+    let mut archive = zip::ZipArchive::new(reader).map_err(io::Error::other)?;
+    let member = archive.by_name(name).map_err(io::Error::other)?;
+    if member.encrypted() {
+        return Err(unsupported("it is encrypted".to_string()));
+    }
+    let start = member
+        .data_start()
+        .ok_or_else(|| unsupported("its data could not be located".to_string()))?;
+    let (method, size) = (member.compression(), member.compressed_size());
+    let (crc32, length) = (member.crc32(), member.size());
+    drop(member);
+
+    // The archive borrows the reader while a member is open, so read the
+    // member's data from the reader itself
+    let mut reader = archive.into_inner();
+    reader.seek(SeekFrom::Start(start))?;
+    let data = reader.take(size);
+    let content: Box<dyn Read + 'a> = match method {
+        CompressionMethod::Stored => Box::new(data),
+        CompressionMethod::Deflated => Box::new(DeflateDecoder::new(data)),
+        method => return Err(unsupported(format!("{} compression", method))),
+    };
+    Ok(Box::new(CrcCheck {
+        inner: content,
+        crc: flate2::Crc::new(),
+        expected: (crc32, length),
+        name: name.to_string(),
+    }))
+}
+
+/// Computes the CRC-32 of a zip member as it is read, and fails at its end
+/// if the CRC or the length differ from the archive's.
+struct CrcCheck<R> {
+    inner: R,
+    crc: flate2::Crc,
+    expected: (u32, u64),
+    name: String,
+}
+
+impl<R: Read> Read for CrcCheck<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc.update(&buf[..read]);
+        let (crc32, length) = self.expected;
+        if read == 0
+            && !buf.is_empty()
+            && (self.crc.sum(), self.crc.amount()) != (crc32, length as u32)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "zip member {:?} is corrupt: its CRC-32 does not match",
+                    self.name
+                ),
+            ));
+        }
+        Ok(read)
+    }
+}
+
+fn is_playlist_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    !name.ends_with('/') && (name.ends_with(".m3u") || name.ends_with(".m3u8"))
+}
+
+/// Compression of the written files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputCompression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl OutputCompression {
+    pub const ALL: [OutputCompression; 2] = [OutputCompression::None, OutputCompression::Gzip];
+
+    /// `path` with the extension of this compression added, e.g.
+    /// `News.m3u.gz`.
+    pub fn file_path(&self, path: &Path) -> PathBuf {
+        match self {
+            OutputCompression::None => path.to_path_buf(),
+            OutputCompression::Gzip => {
+                let mut name = path.as_os_str().to_owned();
+                name.push(".gz");
+                PathBuf::from(name)
+            }
+        }
+    }
+
+    /// Wraps `writer` in an encoder for this compression.
+    pub fn writer<W: Write>(&self, writer: W) -> CompressedWriter<W> {
+        match self {
+            OutputCompression::None => CompressedWriter::Plain(BufWriter::new(writer)),
+            OutputCompression::Gzip => CompressedWriter::Gzip(GzEncoder::new(
+                BufWriter::new(writer),
+                flate2::Compression::default(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputCompression::None => "none",
+            OutputCompression::Gzip => "gz",
+        })
+    }
+}
+
+impl FromStr for OutputCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputCompression::ALL
+            .into_iter()
+            .find(|compression| compression.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown compression {:?}; expected none or gz", s))
+    }
+}
+
+/// A buffered writer that compresses what is written to it.
+pub enum CompressedWriter<W: Write> {
+    Plain(BufWriter<W>),
+    Gzip(GzEncoder<BufWriter<W>>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Writes any remaining compressed data and flushes the underlying
+    /// writer. Dropping the writer without calling this may lose data
+    /// silently.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            CompressedWriter::Plain(mut writer) => writer.flush(),
+            CompressedWriter::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::Plain(writer) => writer.write(buf),
+            CompressedWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::Plain(writer) => writer.flush(),
+            CompressedWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PLAYLIST: &str =
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",News\nhttp://example.com/news\n";
+
+    fn read_all(compressed: Vec<u8>) -> String {
+        let mut content = String::new();
+        decompress(Cursor::new(compressed))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_decompress_by_magic_bytes() {
+        assert_eq!(read_all(PLAYLIST.as_bytes().to_vec()), PLAYLIST);
+
+        let mut gzip = OutputCompression::Gzip.writer(Vec::new());
+        gzip.write_all(PLAYLIST.as_bytes()).unwrap();
+        let CompressedWriter::Gzip(encoder) = gzip else {
+            unreachable!()
+        };
+        let gzip = encoder.finish().unwrap().into_inner().unwrap();
+        assert_eq!(Compression::detect(&gzip), Compression::Gzip);
+        assert_eq!(read_all(gzip), PLAYLIST);
+
+        let xz = {
+            let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(PLAYLIST.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+        assert_eq!(Compression::detect(&xz), Compression::Xz);
+        assert_eq!(read_all(xz), PLAYLIST);
+
+        let zstd = zstd::encode_all(PLAYLIST.as_bytes(), 0).unwrap();
+        assert_eq!(Compression::detect(&zstd), Compression::Zstd);
+        assert_eq!(read_all(zstd), PLAYLIST);
+    }
+
+    #[test]
+    fn test_zip_playlists() {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for name in ["b.m3u8", "readme.txt", "lists/A.M3U"] {
+            let options = match name {
+                "b.m3u8" => options.compression_method(CompressionMethod::Stored),
+                _ => options,
+            };
+            archive.start_file(name, options).unwrap();
+            archive.write_all(PLAYLIST.as_bytes()).unwrap();
+        }
+        let archive = archive.finish().unwrap().into_inner();
+        assert_eq!(Compression::detect(&archive), Compression::Zip);
+
+        let names = zip_playlists(Cursor::new(&archive)).unwrap();
+        assert_eq!(names, vec!["b.m3u8", "lists/A.M3U"]);
+        for name in names {
+            let mut member = String::new();
+            open_zip_member(Cursor::new(&archive), &name)
+                .unwrap()
+                .read_to_string(&mut member)
+                .unwrap();
+            assert_eq!(member, PLAYLIST);
+        }
+        assert!(open_zip_member(Cursor::new(&archive), "missing.m3u").is_err());
+        assert!(decompress(Cursor::new(archive)).is_err());
+    }
+
+    #[test]
+    fn test_zip_member_crc() {
+        for method in [CompressionMethod::Stored, CompressionMethod::Deflated] {
+            let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+            let options = zip::write::SimpleFileOptions::default().compression_method(method);
+            archive.start_file("a.m3u", options).unwrap();
+            archive.write_all(PLAYLIST.repeat(8).as_bytes()).unwrap();
+            let mut archive = archive.finish().unwrap().into_inner();
+
+            // Change one byte of the member's data, just after its local
+            // header and name
+            let data_start = 30 + "a.m3u".len();
+            archive[data_start + 40] ^= 0x01;
+
+            let mut member = Vec::new();
+            let error = open_zip_member(Cursor::new(&archive), "a.m3u")
+                .unwrap()
+                .read_to_end(&mut member)
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn test_output_compression_file_path() {
+        assert_eq!(
+            OutputCompression::Gzip.file_path(Path::new("UK/News.m3u")),
+            PathBuf::from("UK/News.m3u.gz")
+        );
+        assert_eq!(
+            OutputCompression::None.file_path(Path::new("News.m3u")),
+            PathBuf::from("News.m3u")
+        );
+    }
+}
@@ -0,0 +1,424 @@
+//! Detection and removal of duplicate channels.
+
+use crate::playlist::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// What makes two channels duplicates of each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeKey {
+    /// The URL exactly as written.
+    Url,
+    /// The URL without query string or fragment, with the scheme and host
+    /// lowercased and a trailing `/` removed.
+    NormalizedUrl,
+    /// The `tvg-id` attribute. Channels without one are never duplicates.
+    TvgId,
+    /// The title, lowercased, without bracketed notes, quality tags such as
+    /// `HD` or `4K`, and punctuation.
+    Title,
+}
+
+impl DedupeKey {
+    pub const ALL: [DedupeKey; 4] = [
+        DedupeKey::Url,
+        DedupeKey::NormalizedUrl,
+        DedupeKey::TvgId,
+        DedupeKey::Title,
+    ];
+
+    /// The value `entry` is compared by, or `None` if it has none.
+    pub fn value(&self, entry: &Entry) -> Option<String> {
+        let value = match self {
+            DedupeKey::Url => entry.url.trim().to_string(),
+            DedupeKey::NormalizedUrl => normalize_url(&entry.url),
+            DedupeKey::TvgId => entry
+                .attributes
+                .get("tvg-id")
+                .unwrap_or("")
+                .trim()
+                .to_string(),
+            DedupeKey::Title => normalize_title(&entry.title),
+        };
+        Some(value).filter(|value| !value.is_empty())
+    }
+}
+
+impl fmt::Display for DedupeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DedupeKey::Url => "url",
+            DedupeKey::NormalizedUrl => "normalized-url",
+            DedupeKey::TvgId => "tvg-id",
+            DedupeKey::Title => "title",
+        })
+    }
+}
+
+impl FromStr for DedupeKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DedupeKey::ALL
+            .into_iter()
+            .find(|key| key.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown dedupe key {:?}; expected url, normalized-url, tvg-id or title",
+                    s
+                )
+            })
+    }
+}
+
+/// Which of a set of duplicates is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// The first one in the input.
+    #[default]
+    First,
+    /// The last one in the input.
+    Last,
+    /// The one with the most non-blank attributes, or the first of those.
+    Best,
+}
+
+impl KeepPolicy {
+    pub const ALL: [KeepPolicy; 3] = [KeepPolicy::First, KeepPolicy::Last, KeepPolicy::Best];
+}
+
+impl fmt::Display for KeepPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeepPolicy::First => "first",
+            KeepPolicy::Last => "last",
+            KeepPolicy::Best => "best",
+        })
+    }
+}
+
+impl FromStr for KeepPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeepPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown keep policy {:?}; expected first, last or best", s))
+    }
+}
+
+/// Enough of an entry to report it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySummary {
+    /// Position among the entries given to the [`Deduplicator`].
+    pub index: usize,
+    pub title: String,
+    pub group: String,
+    pub url: String,
+}
+
+impl EntrySummary {
+    fn new(index: usize, entry: &Entry) -> Self {
+        EntrySummary {
+            index,
+            title: entry.title.clone(),
+            group: entry.group_name.clone(),
+            url: entry.url.clone(),
+        }
+    }
+}
+
+/// Channels sharing one dedupe value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    /// The value the channels have in common.
+    pub value: String,
+    pub kept: EntrySummary,
+    /// The other channels, in input order.
+    pub removed: Vec<EntrySummary>,
+}
+
+struct Candidate {
+    score: usize,
+    kept: EntrySummary,
+    removed: Vec<EntrySummary>,
+}
+
+/// Finds duplicates among entries seen one at a time.
+///
+/// Deciding which duplicate to keep can take the whole input, so entries
+/// are recorded in one pass and [`Duplicates`] is used to skip the removed
+/// ones in the next.
+pub struct Deduplicator {
+    key: DedupeKey,
+    policy: KeepPolicy,
+    next_index: usize,
+    candidates: HashMap<String, Candidate>,
+    // Dedupe values in order of first appearance
+    order: Vec<String>,
+}
+
+impl Deduplicator {
+    pub fn new(key: DedupeKey, policy: KeepPolicy) -> Self {
+        Deduplicator {
+            key,
+            policy,
+            next_index: 0,
+            candidates: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Records the next entry.
+    pub fn record(&mut self, entry: &Entry) {
+        let index = self.next_index;
+        self.next_index += 1;
+        let Some(value) = self.key.value(entry) else {
+            return;
+        };
+
+        let score = entry
+            .attributes
+            .iter()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .count();
+        let summary = EntrySummary::new(index, entry);
+        let Some(candidate) = self.candidates.get_mut(&value) else {
+            self.order.push(value.clone());
+            self.candidates.insert(
+                value,
+                Candidate {
+                    score,
+                    kept: summary,
+                    removed: Vec::new(),
+                },
+            );
+            return;
+        };
+
+        let replaces = match self.policy {
+            KeepPolicy::First => false,
+            KeepPolicy::Last => true,
+            KeepPolicy::Best => score > candidate.score,
+        };
+        if replaces {
+            candidate.score = score;
+            let previous = std::mem::replace(&mut candidate.kept, summary);
+            candidate.removed.push(previous);
+        } else {
+            candidate.removed.push(summary);
+        }
+    }
+
+    /// Skips the next entry, which is then neither kept nor removed, such
+    /// as one whose groups are all filtered out.
+    pub fn skip(&mut self) {
+        self.next_index += 1;
+    }
+
+    pub fn finish(mut self) -> Duplicates {
+        let mut duplicates = Duplicates::default();
+        for value in self.order {
+            let candidate = self
+                .candidates
+                .remove(&value)
+                .expect("every recorded value has a candidate");
+            if candidate.removed.is_empty() {
+                continue;
+            }
+            let mut removed = candidate.removed;
+            removed.sort_by_key(|summary| summary.index);
+            duplicates
+                .removed
+                .extend(removed.iter().map(|summary| summary.index));
+            duplicates.duplicates.push(Duplicate {
+                value,
+                kept: candidate.kept,
+                removed,
+            });
+        }
+        duplicates
+    }
+}
+
+/// The outcome of a [`Deduplicator`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Duplicates {
+    removed: HashSet<usize>,
+    duplicates: Vec<Duplicate>,
+}
+
+impl Duplicates {
+    /// Whether the entry at `index`, counted the same way as when it was
+    /// recorded, is a removed duplicate.
+    pub fn is_removed(&self, index: usize) -> bool {
+        self.removed.contains(&index)
+    }
+
+    /// Sets of duplicates, in order of first appearance.
+    pub fn iter(&self) -> std::slice::Iter<'_, Duplicate> {
+        self.duplicates.iter()
+    }
+
+    /// Number of removed entries.
+    pub fn removed_count(&self) -> usize {
+        self.removed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+    }
+}
+
+fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let url = url.trim_end_matches('/');
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            format!(
+                "{}://{}{}",
+                scheme.to_ascii_lowercase(),
+                host.to_ascii_lowercase(),
+                path
+            )
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Tags that say how a channel is encoded rather than which channel it is.
+const QUALITY_TAGS: &[&str] = &[
+    "sd", "hd", "fhd", "uhd", "4k", "8k", "hevc", "h264", "h265", "480p", "576p", "720p", "1080p",
+    "1080i", "2160p", "backup",
+];
+
+fn normalize_title(title: &str) -> String {
+    let mut stripped = String::with_capacity(title.len());
+    let mut depth = 0usize;
+    for c in title.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            c if depth == 0 => stripped.extend(c.to_lowercase()),
+            _ => {}
+        }
+    }
+    stripped
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !QUALITY_TAGS.contains(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(extinf_line: &str, url: &str) -> Entry {
+        Entry::new(extinf_line.to_string(), url.to_string())
+    }
+
+    fn sample() -> Vec<Entry> {
+        vec![
+            entry(
+                r#"#EXTINF:-1 group-title="UK",BBC One"#,
+                "http://example.com/bbc1?token=a",
+            ),
+            entry(
+                r#"#EXTINF:-1 tvg-id="bbc1.uk" tvg-logo="bbc.png" group-title="News",BBC One HD"#,
+                "HTTP://Example.com/bbc1?token=b",
+            ),
+            entry(
+                r#"#EXTINF:-1 tvg-id="itv.uk" group-title="UK",ITV"#,
+                "http://example.com/itv",
+            ),
+            entry(
+                r#"#EXTINF:-1 tvg-id="bbc1.uk" group-title="UK",BBC One [Backup]"#,
+                "http://example.com/bbc1/",
+            ),
+        ]
+    }
+
+    fn removed(key: DedupeKey, policy: KeepPolicy) -> Vec<usize> {
+        let mut deduplicator = Deduplicator::new(key, policy);
+        for entry in sample() {
+            deduplicator.record(&entry);
+        }
+        let duplicates = deduplicator.finish();
+        (0..4).filter(|&i| duplicates.is_removed(i)).collect()
+    }
+
+    #[test]
+    fn test_dedupe_keys() {
+        assert_eq!(
+            removed(DedupeKey::Url, KeepPolicy::First),
+            Vec::<usize>::new()
+        );
+        assert_eq!(
+            removed(DedupeKey::NormalizedUrl, KeepPolicy::First),
+            vec![1, 3]
+        );
+        // Entries without a tvg-id are never duplicates
+        assert_eq!(removed(DedupeKey::TvgId, KeepPolicy::First), vec![3]);
+        assert_eq!(removed(DedupeKey::Title, KeepPolicy::First), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_keep_policies() {
+        assert_eq!(removed(DedupeKey::Title, KeepPolicy::Last), vec![0, 1]);
+        // The HD entry has the most attributes
+        assert_eq!(removed(DedupeKey::Title, KeepPolicy::Best), vec![0, 3]);
+    }
+
+    #[test]
+    fn test_skipped_entries() {
+        let mut deduplicator = Deduplicator::new(DedupeKey::Title, KeepPolicy::First);
+        for (index, entry) in sample().iter().enumerate() {
+            if index == 0 {
+                deduplicator.skip();
+            } else {
+                deduplicator.record(entry);
+            }
+        }
+        let duplicates = deduplicator.finish();
+
+        // The skipped copy no longer hides the next one
+        assert!(!duplicates.is_removed(0));
+        assert!(!duplicates.is_removed(1));
+        assert!(duplicates.is_removed(3));
+    }
+
+    #[test]
+    fn test_duplicates_report() {
+        let mut deduplicator = Deduplicator::new(DedupeKey::Title, KeepPolicy::Best);
+        for entry in sample() {
+            deduplicator.record(&entry);
+        }
+        let duplicates = deduplicator.finish();
+
+        assert_eq!(duplicates.removed_count(), 2);
+        let duplicate = duplicates.iter().next().unwrap();
+        assert_eq!(duplicate.value, "bbc one");
+        assert_eq!(duplicate.kept.title, "BBC One HD");
+        assert_eq!(duplicate.kept.group, "News");
+        let removed: Vec<&str> = duplicate.removed.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(removed, vec!["BBC One", "BBC One [Backup]"]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize_url(" HTTPS://Example.COM/Live/1.m3u8?token=x#t "),
+            "https://example.com/Live/1.m3u8"
+        );
+        assert_eq!(normalize_title("BBC One (UK) FHD"), "bbc one");
+        assert_eq!(normalize_title("Sky Sports: F1 | 1080p"), "sky sports f1");
+        assert!(DedupeKey::Title
+            .value(&entry("#EXTINF:-1,HD", "x"))
+            .is_none());
+    }
+}
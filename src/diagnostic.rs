@@ -0,0 +1,60 @@
+//! Problems found while parsing a playlist.
+
+use std::fmt;
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Something unexpected that was read anyway, or skipped without losing
+    /// a channel.
+    Warning,
+    /// A channel was lost.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// An anomaly in a playlist, such as an `#EXTINF` without a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The input the problem was found in, if known.
+    pub file: Option<String>,
+    /// The 1-based line number.
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(line: usize, severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            file: None,
+            line,
+            severity,
+            message: message.into(),
+        }
+    }
+
+    /// Sets the input the problem was found in.
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+}
+
+/// Formats as `file:line: severity: message`, like a compiler.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}: {}: {}", self.line, self.severity, self.message)
+    }
+}
@@ -0,0 +1,321 @@
+//! Decoding input playlists from any text encoding into UTF-8.
+
+use encoding_rs::{
+    CoderResult, Decoder, DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252,
+};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::str::FromStr;
+
+/// How many bytes [`InputEncoding::Auto`] looks at to guess the encoding;
+/// an input guessed to be UTF-8 can still turn out to be Windows-1252 later.
+const SNIFF_LEN: u64 = 64 * 1024;
+
+/// The text encoding of an input playlist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// Guess from the start of the input, see [`detect_encoding`], and
+    /// switch from UTF-8 to Windows-1252 at the first byte that is not
+    /// valid UTF-8.
+    #[default]
+    Auto,
+    /// A fixed encoding, such as `windows-1252` or `iso-8859-2`.
+    Fixed(&'static Encoding),
+}
+
+impl fmt::Display for InputEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputEncoding::Auto => f.write_str("auto"),
+            InputEncoding::Fixed(encoding) => f.write_str(&encoding.name().to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for InputEncoding {
+    type Err = String;
+
+    /// Parses `auto` or any label of the WHATWG Encoding Standard, such as
+    /// `utf-8`, `utf-16le`, `latin1` or `cp1251`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(InputEncoding::Auto);
+        }
+        Encoding::for_label(s.as_bytes())
+            .map(InputEncoding::Fixed)
+            .ok_or_else(|| format!("unknown encoding {:?}; expected auto or a label such as utf-8, windows-1252 or iso-8859-2", s))
+    }
+}
+
+/// Guesses the encoding of a playlist from its first bytes: the byte order
+/// mark if there is one, otherwise UTF-16 if most of every other byte is
+/// zero, UTF-8 if the bytes are valid UTF-8, and Windows-1252 (a superset
+/// of Latin-1) for anything else.
+pub fn detect_encoding(prefix: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(prefix) {
+        return encoding;
+    }
+    // Playlists never contain NUL characters, but UTF-16 has one in every
+    // other byte for ASCII text, which is also valid UTF-8
+    let pairs = prefix.len() / 2;
+    let zeros_at = |offset: usize| {
+        prefix
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    if pairs > 0 && zeros_at(1) * 2 > pairs {
+        return UTF_16LE;
+    }
+    if pairs > 0 && zeros_at(0) * 2 > pairs {
+        return UTF_16BE;
+    }
+
+    match std::str::from_utf8(prefix) {
+        Ok(_) => UTF_8,
+        // A multi-byte sequence cut off at the end of the prefix
+        Err(e) if e.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+/// Wraps `reader` so that it yields UTF-8, decoding from `encoding`. A byte
+/// order mark is removed and takes precedence over `encoding`. Bytes that
+/// are invalid in the encoding fail the read with
+/// [`io::ErrorKind::InvalidData`], or are replaced with U+FFFD when `lossy`
+/// is set. An input guessed to be UTF-8 without a byte order mark is read
+/// as Windows-1252 from its first invalid byte on, wherever that is.
+pub fn decode<'a, R: Read + 'a>(
+    mut reader: R,
+    encoding: InputEncoding,
+    lossy: bool,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut prefix = Vec::new();
+    (&mut reader).take(SNIFF_LEN).read_to_end(&mut prefix)?;
+    let (encoding, fallback) = match encoding {
+        InputEncoding::Auto => {
+            let encoding = detect_encoding(&prefix);
+            let bom = Encoding::for_bom(&prefix).is_some();
+            (encoding, encoding == UTF_8 && !bom)
+        }
+        InputEncoding::Fixed(encoding) => (encoding, false),
+    };
+
+    Ok(Box::new(BufReader::new(DecodeReader {
+        inner: Cursor::new(prefix).chain(reader),
+        decoder: encoding.new_decoder(),
+        lossy,
+        fallback,
+        input: vec![0; 8 * 1024],
+        start: 0,
+        end: 0,
+        eof: false,
+        partial: false,
+        decoded: 0,
+        output: String::new(),
+        pos: 0,
+    })))
+}
+
+struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    lossy: bool,
+    // Whether the input is UTF-8 until proven otherwise, in which case it is
+    // validated here instead of by the decoder
+    fallback: bool,
+    // Bytes read but not decoded yet are input[start..end]
+    input: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+    // Whether input[start..end] ends with an incomplete UTF-8 sequence that
+    // needs more bytes to be validated
+    partial: bool,
+    // Bytes decoded so far, for error messages
+    decoded: usize,
+    // Decoded text not handed out yet is output[pos..]
+    output: String,
+    pos: usize,
+}
+
+impl<R: Read> DecodeReader<R> {
+    /// Decodes the next chunk into `output`, leaving it empty at the end.
+    fn fill(&mut self) -> io::Result<()> {
+        self.output.clear();
+        self.pos = 0;
+        while self.output.is_empty() {
+            if self.start == self.end || self.partial {
+                if self.eof {
+                    return Ok(());
+                }
+                // Keep the bytes not decoded yet
+                self.input.copy_within(self.start..self.end, 0);
+                self.end -= self.start;
+                self.start = 0;
+                let read = self.inner.read(&mut self.input[self.end..])?;
+                self.eof = read == 0;
+                self.end += read;
+                self.partial = false;
+            }
+
+            let src = &self.input[self.start..self.end];
+            let needed = self
+                .decoder
+                .max_utf8_buffer_length(src.len())
+                .unwrap_or(src.len() * 3 + 16);
+            self.output.reserve(needed);
+            let read = if self.fallback {
+                let (valid, invalid) = match std::str::from_utf8(src) {
+                    Ok(text) => (text, false),
+                    Err(e) => {
+                        let valid = std::str::from_utf8(&src[..e.valid_up_to()]).unwrap();
+                        (valid, e.error_len().is_some() || self.eof)
+                    }
+                };
+                self.output.push_str(valid);
+                if invalid {
+                    self.fallback = false;
+                    self.decoder = WINDOWS_1252.new_decoder_without_bom_handling();
+                } else {
+                    self.partial = valid.len() < src.len();
+                }
+                valid.len()
+            } else if self.lossy {
+                let (result, read, _) =
+                    self.decoder
+                        .decode_to_string(src, &mut self.output, self.eof);
+                debug_assert_eq!(result, CoderResult::InputEmpty);
+                read
+            } else {
+                let (result, read) = self.decoder.decode_to_string_without_replacement(
+                    src,
+                    &mut self.output,
+                    self.eof,
+                );
+                if let DecoderResult::Malformed(bad, after) = result {
+                    let offset = self.decoded + read - bad as usize - after as usize;
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "input is not valid {} at byte {}",
+                            self.decoder.encoding().name(),
+                            offset
+                        ),
+                    ));
+                }
+                read
+            };
+            self.start += read;
+            self.decoded += read;
+            if self.eof && self.start == self.end {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.output.len() {
+            self.fill()?;
+        }
+        let available = &self.output.as_bytes()[self.pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(bytes: &[u8], encoding: InputEncoding, lossy: bool) -> io::Result<String> {
+        let mut text = String::new();
+        decode(bytes, encoding, lossy)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"#EXTM3U\n"), UTF_8);
+        assert_eq!(detect_encoding("Café".as_bytes()), UTF_8);
+        // Cut off in the middle of "é"
+        assert_eq!(detect_encoding(&"Café".as_bytes()[..4]), UTF_8);
+        assert_eq!(detect_encoding(b"Caf\xe9\n"), WINDOWS_1252);
+        assert_eq!(detect_encoding(b"#\0E\0X\0T\0"), UTF_16LE);
+        assert_eq!(detect_encoding(b"\0#\0E\0X\0T"), UTF_16BE);
+        assert_eq!(detect_encoding(b"\xff\xfe#\0"), UTF_16LE);
+    }
+
+    #[test]
+    fn test_decode_strips_bom() {
+        let text = decode_all(b"\xef\xbb\xbf#EXTM3U\n", InputEncoding::Auto, false).unwrap();
+        assert_eq!(text, "#EXTM3U\n");
+
+        // The byte order mark wins over the given encoding
+        let latin1 = "latin1".parse().unwrap();
+        let text = decode_all(b"\xef\xbb\xbf#EXTM3U \xc3\xa9\n", latin1, false).unwrap();
+        assert_eq!(text, "#EXTM3U é\n");
+    }
+
+    #[test]
+    fn test_decode_encodings() {
+        let text = decode_all(b"Caf\xe9 \x80\n", InputEncoding::Auto, false).unwrap();
+        assert_eq!(text, "Café €\n");
+
+        let utf16: Vec<u8> = "Café\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let text = decode_all(&utf16, InputEncoding::Auto, false).unwrap();
+        assert_eq!(text, "Café\n");
+
+        let cp1251 = "cp1251".parse().unwrap();
+        let text = decode_all(b"\xcd\xee\xe2\xee\xf1\xf2\xe8\n", cp1251, false).unwrap();
+        assert_eq!(text, "Новости\n");
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        let utf8 = "utf-8".parse().unwrap();
+        let err = decode_all(b"#EXTM3U\nCaf\xe9\n", utf8, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("at byte 11"));
+
+        let text = decode_all(b"#EXTM3U\nCaf\xe9\n", utf8, true).unwrap();
+        assert_eq!(text, "#EXTM3U\nCaf\u{fffd}\n");
+    }
+
+    #[test]
+    fn test_decode_falls_back_after_sniffing() {
+        // Guessed to be UTF-8 from the start, then a Windows-1252 byte
+        let mut bytes = "Café\n".repeat(SNIFF_LEN as usize / 4).into_bytes();
+        bytes.extend_from_slice(b"Caf\xe9 \x80\n");
+        let text = decode_all(&bytes, InputEncoding::Auto, false).unwrap();
+        assert!(text.ends_with("Café\nCafé €\n"));
+        assert_eq!(text.len(), bytes.len() + "é€".len() - 2);
+
+        // An incomplete sequence at the very end
+        let mut bytes = b"#".repeat(SNIFF_LEN as usize + 10);
+        bytes.push(0xc3);
+        let text = decode_all(&bytes, InputEncoding::Auto, false).unwrap();
+        assert!(text.ends_with("#Ã"));
+
+        // The byte order mark rules out falling back
+        let mut bytes = b"\xef\xbb\xbf".to_vec();
+        bytes.extend(b"#".repeat(SNIFF_LEN as usize));
+        bytes.push(0xe9);
+        assert!(decode_all(&bytes, InputEncoding::Auto, false).is_err());
+    }
+
+    #[test]
+    fn test_input_encoding_from_str() {
+        assert_eq!("AUTO".parse::<InputEncoding>(), Ok(InputEncoding::Auto));
+        assert_eq!(
+            "latin1".parse::<InputEncoding>().unwrap().to_string(),
+            "windows-1252"
+        );
+        assert!("klingon".parse::<InputEncoding>().is_err());
+    }
+}
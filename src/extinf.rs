@@ -0,0 +1,608 @@
+//! Tokenizer for `#EXTINF` lines.
+
+use std::fmt;
+use std::ops::Range;
+
+/// Ordered list of `key=value` attributes found on an `#EXTINF` line.
+///
+/// Order is the order in which the attributes appear in the source line.
+/// Lookups are ASCII case-insensitive on the key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes(Vec<(String, String)>);
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets `key` to `value`, replacing an existing value in place or
+    /// appending a new attribute at the end.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        let value = value.into();
+        match self
+            .0
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(&key))
+        {
+            Some(entry) => entry.1 = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let pos = self
+            .0
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(key))?;
+        Some(self.0.remove(pos).1)
+    }
+
+    /// Keeps only the attributes for which `keep` returns true.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str, &str) -> bool) {
+        self.0.retain(|(k, v)| keep(k, v));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Parsed contents of a `#EXTINF:<duration> key="value" ...,Title` line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extinf {
+    pub duration: f64,
+    pub attributes: Attributes,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtinfError {
+    /// The line does not start with `#EXTINF:`.
+    MissingPrefix,
+    /// The duration field is empty or not a number.
+    InvalidDuration(String),
+    /// A quoted attribute value is never closed.
+    UnterminatedQuote(String),
+}
+
+impl fmt::Display for ExtinfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtinfError::MissingPrefix => write!(f, "line does not start with #EXTINF:"),
+            ExtinfError::InvalidDuration(d) => write!(f, "invalid duration {:?}", d),
+            ExtinfError::UnterminatedQuote(key) => {
+                write!(f, "unterminated quoted value for attribute {:?}", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExtinfError {}
+
+/// An error from [`parse_header`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The line does not start with `#EXTM3U`.
+    MissingPrefix,
+    /// A quoted attribute value is never closed.
+    UnterminatedQuote(String),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::MissingPrefix => write!(f, "line does not start with #EXTM3U"),
+            HeaderError::UnterminatedQuote(key) => {
+                write!(f, "unterminated quoted value for attribute {:?}", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+// The error of an attribute list, converted into the error type of the
+// line it is part of
+struct UnterminatedQuote(String);
+
+impl From<UnterminatedQuote> for ExtinfError {
+    fn from(error: UnterminatedQuote) -> Self {
+        ExtinfError::UnterminatedQuote(error.0)
+    }
+}
+
+impl From<UnterminatedQuote> for HeaderError {
+    fn from(error: UnterminatedQuote) -> Self {
+        HeaderError::UnterminatedQuote(error.0)
+    }
+}
+
+/// Tokenizes an `#EXTINF` line into its duration, attributes and title.
+///
+/// Attribute values may be double-quoted (with `\"` and `\\` escapes),
+/// single-quoted or unquoted. A key without `=` is kept with an empty
+/// value. The title is everything after the first comma that is not part
+/// of a quoted value.
+pub fn parse_extinf(line: &str) -> Result<Extinf, ExtinfError> {
+    let scanned = scan(line)?;
+    let mut attributes = Attributes::new();
+    for attribute in scanned.attributes {
+        attributes.insert(attribute.key, attribute.value);
+    }
+    Ok(Extinf {
+        duration: scanned.duration,
+        attributes,
+        title: scanned.title,
+    })
+}
+
+/// Returns `line` with attribute `key` set to `value`, leaving the rest of
+/// the line untouched. An existing value is replaced where it stands; a new
+/// attribute is added after the others, before the title.
+pub fn set_extinf_attribute(line: &str, key: &str, value: &str) -> Result<String, ExtinfError> {
+    let scanned = scan(line)?;
+    let escaped = escape(value);
+
+    let mut matching = scanned
+        .attributes
+        .iter()
+        .filter(|attribute| attribute.key.eq_ignore_ascii_case(key))
+        .peekable();
+    if matching.peek().is_none() {
+        let at = scanned.attributes_end;
+        return Ok(format!(
+            "{} {}=\"{}\"{}",
+            &line[..at],
+            key,
+            escaped,
+            &line[at..]
+        ));
+    }
+
+    let mut rewritten = String::with_capacity(line.len() + value.len());
+    let mut last = 0;
+    for attribute in matching {
+        rewritten.push_str(&line[last..attribute.span.start]);
+        rewritten.push_str(&format!("{}=\"{}\"", attribute.key, escaped));
+        last = attribute.span.end;
+    }
+    rewritten.push_str(&line[last..]);
+    Ok(rewritten)
+}
+
+/// Returns `line` without attribute `key`, leaving the rest of the line
+/// untouched.
+pub fn remove_extinf_attribute(line: &str, key: &str) -> Result<String, ExtinfError> {
+    let scanned = scan(line)?;
+    let mut removed = String::with_capacity(line.len());
+    let mut last = 0;
+    for attribute in &scanned.attributes {
+        if attribute.key.eq_ignore_ascii_case(key) {
+            // Drop the whitespace before the attribute along with it
+            removed.push_str(line[last..attribute.span.start].trim_end());
+            last = attribute.span.end;
+        }
+    }
+    removed.push_str(&line[last..]);
+    Ok(removed)
+}
+
+/// Parses the attributes of an `#EXTM3U` header line, such as the EPG link
+/// in `#EXTM3U url-tvg="http://example.com/epg.xml" tvg-shift="1"`, with
+/// the same quoting rules as [`parse_extinf`].
+pub fn parse_header(line: &str) -> Result<Attributes, HeaderError> {
+    let trimmed = line.trim();
+    let rest = trimmed
+        .strip_prefix("#EXTM3U")
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        .ok_or(HeaderError::MissingPrefix)?;
+    let base = line.len() - line.trim_start().len() + "#EXTM3U".len();
+
+    let (scanned, _, _) = scan_attributes(&mut Cursor::new(rest), base)?;
+    let mut attributes = Attributes::new();
+    for attribute in scanned {
+        attributes.insert(attribute.key, attribute.value);
+    }
+    Ok(attributes)
+}
+
+/// Formats an `#EXTM3U` header line with `attributes`, double-quoting
+/// every value.
+pub fn format_header(attributes: &Attributes) -> String {
+    let mut line = String::from("#EXTM3U");
+    for (key, value) in attributes.iter() {
+        line.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+    }
+    line
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct ScannedAttribute {
+    key: String,
+    value: String,
+    /// Byte range of `key=value` in the line.
+    span: Range<usize>,
+}
+
+struct Scanned {
+    duration: f64,
+    attributes: Vec<ScannedAttribute>,
+    /// Byte offset in the line where the attribute list ends: the title
+    /// comma, or the end of the content if there is no title.
+    attributes_end: usize,
+    title: String,
+}
+
+fn scan(line: &str) -> Result<Scanned, ExtinfError> {
+    let trimmed = line.trim();
+    let rest = trimmed
+        .strip_prefix("#EXTINF:")
+        .ok_or(ExtinfError::MissingPrefix)?;
+    // Offset of `rest` within `line`
+    let base = line.len() - line.trim_start().len() + "#EXTINF:".len();
+    let mut cursor = Cursor::new(rest);
+
+    cursor.skip_whitespace();
+    let raw_duration = cursor.take_while(|c| !c.is_whitespace() && c != ',');
+    let duration = raw_duration
+        .parse::<f64>()
+        .map_err(|_| ExtinfError::InvalidDuration(raw_duration.to_string()))?;
+
+    let (attributes, attributes_end, title) = scan_attributes(&mut cursor, base)?;
+
+    Ok(Scanned {
+        duration,
+        attributes,
+        attributes_end,
+        title,
+    })
+}
+
+/// Scans `key=value` attributes from `cursor` up to an optional `,Title`,
+/// returning them with the offset where they end and the title. `base` is
+/// the offset of the cursor's input within the line.
+fn scan_attributes(
+    cursor: &mut Cursor<'_>,
+    base: usize,
+) -> Result<(Vec<ScannedAttribute>, usize, String), UnterminatedQuote> {
+    let mut attributes = Vec::new();
+    let mut title = String::new();
+    let mut attributes_end = base + cursor.input.len();
+
+    loop {
+        cursor.skip_whitespace();
+        match cursor.peek() {
+            None => break,
+            Some(',') => {
+                attributes_end = base + cursor.pos;
+                cursor.bump();
+                title = cursor.remaining().trim().to_string();
+                break;
+            }
+            Some(_) => {}
+        }
+
+        let start = cursor.pos;
+        let key = cursor
+            .take_while(|c| c != '=' && c != ',' && !c.is_whitespace())
+            .to_string();
+        if cursor.peek() != Some('=') {
+            attributes.push(ScannedAttribute {
+                key,
+                value: String::new(),
+                span: base + start..base + cursor.pos,
+            });
+            continue;
+        }
+        cursor.bump();
+
+        let value = match cursor.peek() {
+            Some('"') => {
+                cursor.bump();
+                cursor
+                    .take_quoted('"', true)
+                    .ok_or_else(|| UnterminatedQuote(key.clone()))?
+            }
+            Some('\'') => {
+                cursor.bump();
+                cursor
+                    .take_quoted('\'', false)
+                    .ok_or_else(|| UnterminatedQuote(key.clone()))?
+            }
+            _ => cursor
+                .take_while(|c| c != ',' && !c.is_whitespace())
+                .to_string(),
+        };
+        attributes.push(ScannedAttribute {
+            key,
+            value,
+            span: base + start..base + cursor.pos,
+        });
+    }
+
+    Ok((attributes, attributes_end, title))
+}
+
+struct Cursor<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn remaining(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn take_while(&mut self, mut pred: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !pred(c) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        &self.input[start..self.pos]
+    }
+
+    /// Reads up to the closing `quote`, consuming it. Returns `None` if the
+    /// input ends first.
+    fn take_quoted(&mut self, quote: char, escapes: bool) -> Option<String> {
+        let mut value = String::new();
+        loop {
+            match self.bump()? {
+                c if c == quote => return Some(value),
+                '\\' if escapes => match self.peek() {
+                    Some(next) if next == quote || next == '\\' => {
+                        self.bump();
+                        value.push(next);
+                    }
+                    _ => value.push('\\'),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_group_name(extinf_line: &str) -> Option<String> {
+        parse_extinf(extinf_line)
+            .ok()?
+            .attributes
+            .get("group-title")
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn test_parse_extinf_full_line() {
+        let extinf = parse_extinf(
+            r#"#EXTINF:-1 tvg-id="bbc1.uk" tvg-logo="http://x/logo.png" group-title="News",BBC One"#,
+        )
+        .unwrap();
+        assert_eq!(extinf.duration, -1.0);
+        assert_eq!(extinf.title, "BBC One");
+        let keys: Vec<_> = extinf.attributes.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["tvg-id", "tvg-logo", "group-title"]);
+        assert_eq!(extinf.attributes.get("group-title"), Some("News"));
+    }
+
+    #[test]
+    fn test_parse_extinf_does_not_match_key_suffix() {
+        let extinf = parse_extinf(r#"#EXTINF:-1 xgroup-title="Wrong",Title"#).unwrap();
+        assert_eq!(extinf.attributes.get("group-title"), None);
+        assert_eq!(extinf.attributes.get("xgroup-title"), Some("Wrong"));
+    }
+
+    #[test]
+    fn test_parse_extinf_escaped_quotes() {
+        let extinf =
+            parse_extinf(r#"#EXTINF:-1 group-title="The \"Best\" \\ Shows",Title"#).unwrap();
+        assert_eq!(
+            extinf.attributes.get("group-title"),
+            Some(r#"The "Best" \ Shows"#)
+        );
+    }
+
+    #[test]
+    fn test_parse_extinf_unquoted_and_bare_values() {
+        let extinf = parse_extinf("#EXTINF:10.5 tvg-shift=2 catchup,Title").unwrap();
+        assert_eq!(extinf.duration, 10.5);
+        assert_eq!(extinf.attributes.get("tvg-shift"), Some("2"));
+        assert_eq!(extinf.attributes.get("catchup"), Some(""));
+    }
+
+    #[test]
+    fn test_parse_extinf_comma_inside_quotes() {
+        let extinf = parse_extinf(r#"#EXTINF:-1 group-title="News, Weather",Local, HD"#).unwrap();
+        assert_eq!(extinf.attributes.get("group-title"), Some("News, Weather"));
+        assert_eq!(extinf.title, "Local, HD");
+    }
+
+    #[test]
+    fn test_parse_extinf_no_attributes() {
+        let extinf = parse_extinf("#EXTINF:123,Artist - Song").unwrap();
+        assert_eq!(extinf.duration, 123.0);
+        assert!(extinf.attributes.is_empty());
+        assert_eq!(extinf.title, "Artist - Song");
+    }
+
+    #[test]
+    fn test_parse_extinf_errors() {
+        assert_eq!(parse_extinf("#EXTM3U"), Err(ExtinfError::MissingPrefix));
+        assert_eq!(
+            parse_extinf("#EXTINF:abc,Title"),
+            Err(ExtinfError::InvalidDuration("abc".to_string()))
+        );
+        assert_eq!(
+            parse_extinf(r#"#EXTINF:-1 group-title="Open,Title"#),
+            Err(ExtinfError::UnterminatedQuote("group-title".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_group_name_double_quotes() {
+        let line = r#"#EXTINF:-1 group-title="Sports" tvg-id="channel1",Channel Name"#;
+        assert_eq!(parse_group_name(line), Some("Sports".to_string()));
+    }
+
+    #[test]
+    fn test_parse_group_name_single_quotes() {
+        let line = r#"#EXTINF:-1 group-title='News' tvg-id="channel2",Another Channel"#;
+        assert_eq!(parse_group_name(line), Some("News".to_string()));
+    }
+
+    #[test]
+    fn test_parse_group_name_with_spaces() {
+        let line = r#"#EXTINF:-1 group-title="Kids & Family" tvg-id="channel3",Kids Channel"#;
+        assert_eq!(parse_group_name(line), Some("Kids & Family".to_string()));
+    }
+
+    #[test]
+    fn test_parse_group_name_missing() {
+        let line = r#"#EXTINF:-1 tvg-id="channel4",Channel Without Group"#;
+        assert_eq!(parse_group_name(line), None);
+    }
+
+    #[test]
+    fn test_parse_group_name_empty() {
+        let line = r#"#EXTINF:-1 group-title="" tvg-id="channel5",Empty Group"#;
+        assert_eq!(parse_group_name(line), Some("".to_string()));
+    }
+
+    #[test]
+    fn test_parse_group_name_special_characters() {
+        let line = r#"#EXTINF:-1 group-title="Café & Música" tvg-id="channel6",Special"#;
+        assert_eq!(parse_group_name(line), Some("Café & Música".to_string()));
+    }
+
+    #[test]
+    fn test_set_extinf_attribute_replaces_value_in_place() {
+        let line = r#"#EXTINF:-1 tvg-id="a" group-title='UK | Sports' tvg-logo=x,Sky Sports"#;
+        assert_eq!(
+            set_extinf_attribute(line, "group-title", "Sports-UK").unwrap(),
+            r#"#EXTINF:-1 tvg-id="a" group-title="Sports-UK" tvg-logo=x,Sky Sports"#
+        );
+        assert_eq!(
+            set_extinf_attribute(line, "tvg-id", r#"say "hi""#).unwrap(),
+            r#"#EXTINF:-1 tvg-id="say \"hi\"" group-title='UK | Sports' tvg-logo=x,Sky Sports"#
+        );
+    }
+
+    #[test]
+    fn test_set_extinf_attribute_adds_missing_attribute() {
+        assert_eq!(
+            set_extinf_attribute(
+                r#"#EXTINF:-1 tvg-id="a",News, Weather"#,
+                "group-title",
+                "News"
+            )
+            .unwrap(),
+            r#"#EXTINF:-1 tvg-id="a" group-title="News",News, Weather"#
+        );
+        assert_eq!(
+            set_extinf_attribute("#EXTINF:-1", "group-title", "News").unwrap(),
+            r#"#EXTINF:-1 group-title="News""#
+        );
+        assert!(set_extinf_attribute("#EXTM3U", "group-title", "News").is_err());
+    }
+
+    #[test]
+    fn test_remove_extinf_attribute() {
+        let line = r#"#EXTINF:-1 tvg-id="a" group-title="News" tvg-logo="x",News 1"#;
+        assert_eq!(
+            remove_extinf_attribute(line, "GROUP-TITLE").unwrap(),
+            r#"#EXTINF:-1 tvg-id="a" tvg-logo="x",News 1"#
+        );
+        assert_eq!(remove_extinf_attribute(line, "missing").unwrap(), line);
+        assert_eq!(
+            remove_extinf_attribute(r#"#EXTINF:-1 group-title="News",News 1"#, "group-title")
+                .unwrap(),
+            "#EXTINF:-1,News 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header =
+            parse_header(r#"#EXTM3U url-tvg="http://example.com/epg.xml" tvg-shift=1"#).unwrap();
+        assert_eq!(header.get("url-tvg"), Some("http://example.com/epg.xml"));
+        assert_eq!(header.get("tvg-shift"), Some("1"));
+        assert!(parse_header("#EXTM3U").unwrap().is_empty());
+
+        assert_eq!(parse_header("#EXTM3Ux"), Err(HeaderError::MissingPrefix));
+        assert_eq!(
+            parse_header(r#"#EXTM3U url-tvg="http://"#),
+            Err(HeaderError::UnterminatedQuote("url-tvg".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_format_header() {
+        let mut header = Attributes::new();
+        assert_eq!(format_header(&header), "#EXTM3U");
+        header.insert("url-tvg", "http://example.com/epg.xml");
+        header.insert("x-note", r#"say "hi""#);
+        let line = format_header(&header);
+        assert_eq!(
+            line,
+            r#"#EXTM3U url-tvg="http://example.com/epg.xml" x-note="say \"hi\"""#
+        );
+        assert_eq!(parse_header(&line).unwrap(), header);
+    }
+
+    #[test]
+    fn test_attributes_insert_replaces_in_place() {
+        let mut attributes = Attributes::new();
+        attributes.insert("tvg-id", "a");
+        attributes.insert("group-title", "Sports");
+        attributes.insert("TVG-ID", "b");
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes.get("tvg-id"), Some("b"));
+        assert_eq!(attributes.remove("group-title"), Some("Sports".to_string()));
+        assert!(!attributes.contains_key("group-title"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Output file naming.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How group names are turned into file and directory names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilenameStrategy {
+    /// Keep ASCII letters, digits, `-` and `_`; spaces become `_` and
+    /// everything else is dropped ("Música" gives "Msica").
+    #[default]
+    Ascii,
+    /// Keep Unicode and strip only characters the OS rejects ("北京" stays
+    /// "北京").
+    Unicode,
+    /// Transliterate to ASCII first ("Café" gives "Cafe", "Москва" gives
+    /// "Moskva"), then apply the ASCII rules.
+    Transliterate,
+    /// Lowercase transliterated words joined by `-` ("Kids & Family" gives
+    /// "kids-family").
+    Slug,
+}
+
+impl FilenameStrategy {
+    pub const ALL: [FilenameStrategy; 4] = [
+        FilenameStrategy::Ascii,
+        FilenameStrategy::Unicode,
+        FilenameStrategy::Transliterate,
+        FilenameStrategy::Slug,
+    ];
+
+    /// Applies the strategy to `name`. The result may be empty.
+    pub fn sanitize(&self, name: &str) -> String {
+        match self {
+            FilenameStrategy::Ascii => sanitize_filename(name),
+            FilenameStrategy::Unicode => sanitize_unicode(name),
+            FilenameStrategy::Transliterate => sanitize_filename(&deunicode::deunicode(name)),
+            FilenameStrategy::Slug => slugify(&deunicode::deunicode(name)),
+        }
+    }
+
+    /// Applies the strategy to `name`, falling back to a stable name derived
+    /// from a hash of `name` when nothing usable is left.
+    pub fn file_stem(&self, name: &str) -> String {
+        let stem = self.sanitize(name);
+        if stem.is_empty() {
+            hashed_name(name)
+        } else {
+            stem
+        }
+    }
+}
+
+impl fmt::Display for FilenameStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FilenameStrategy::Ascii => "ascii",
+            FilenameStrategy::Unicode => "unicode",
+            FilenameStrategy::Transliterate => "transliterate",
+            FilenameStrategy::Slug => "slug",
+        })
+    }
+}
+
+impl FromStr for FilenameStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FilenameStrategy::ALL
+            .into_iter()
+            .find(|strategy| strategy.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown filename strategy {:?}; expected ascii, unicode, transliterate or slug",
+                    s
+                )
+            })
+    }
+}
+
+/// Turns a group name into a safe file stem.
+pub fn sanitize_filename(group_name: &str) -> String {
+    // Remove non-ASCII characters and keep only safe filesystem characters
+    group_name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_' || *c == ' ')
+        .collect::<String>()
+        .trim()
+        .replace(' ', "_")
+}
+
+/// Strips characters rejected by Windows or Unix file systems, trailing
+/// dots and spaces, and suffixes names reserved on Windows.
+fn sanitize_unicode(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| {
+            !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*')
+        })
+        .collect();
+    let mut cleaned = cleaned
+        .trim_start()
+        .trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string();
+
+    let base_len = cleaned.find('.').unwrap_or(cleaned.len());
+    if is_reserved_windows_name(&cleaned[..base_len]) {
+        cleaned.insert(base_len, '_');
+    }
+    cleaned
+}
+
+fn is_reserved_windows_name(base: &str) -> bool {
+    let upper = base.to_ascii_uppercase();
+    match upper.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => {
+            (upper.starts_with("COM") || upper.starts_with("LPT"))
+                && upper.len() == 4
+                && matches!(upper.as_bytes()[3], b'1'..=b'9')
+        }
+    }
+}
+
+fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// `group-<hash>` using 64-bit FNV-1a, which unlike the std hasher gives the
+/// same value on every platform and Rust release.
+fn hashed_name(name: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in name.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("group-{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename_simple() {
+        assert_eq!(sanitize_filename("Sports"), "Sports");
+    }
+
+    #[test]
+    fn test_sanitize_filename_with_spaces() {
+        assert_eq!(sanitize_filename("Kids & Family"), "Kids__Family");
+    }
+
+    #[test]
+    fn test_sanitize_filename_non_ascii() {
+        assert_eq!(sanitize_filename("Café"), "Caf");
+        assert_eq!(sanitize_filename("Música"), "Msica");
+        assert_eq!(sanitize_filename("北京"), "");
+    }
+
+    #[test]
+    fn test_sanitize_filename_special_chars() {
+        assert_eq!(sanitize_filename("Group/Name"), "GroupName");
+        assert_eq!(sanitize_filename("Group\\Name"), "GroupName");
+        assert_eq!(sanitize_filename("Group*Name"), "GroupName");
+    }
+
+    #[test]
+    fn test_sanitize_filename_leading_trailing_spaces() {
+        assert_eq!(sanitize_filename("  Sports  "), "Sports");
+    }
+
+    #[test]
+    fn test_sanitize_filename_dashes_and_underscores() {
+        assert_eq!(sanitize_filename("group-name"), "group-name");
+        assert_eq!(sanitize_filename("group_name"), "group_name");
+        assert_eq!(sanitize_filename("group-name_test"), "group-name_test");
+    }
+
+    #[test]
+    fn test_strategy_unicode_keeps_letters() {
+        let strategy = FilenameStrategy::Unicode;
+        assert_eq!(strategy.sanitize("北京"), "北京");
+        assert_eq!(strategy.sanitize("Música"), "Música");
+        assert_eq!(strategy.sanitize("News: UK/Ireland?"), "News UKIreland");
+        assert_eq!(strategy.sanitize("  Sports... "), "Sports");
+        assert_eq!(strategy.sanitize("con"), "con_");
+        assert_eq!(strategy.sanitize("COM1.txt"), "COM1_.txt");
+        assert_eq!(strategy.sanitize("a€b"), "a€b");
+        assert_eq!(strategy.sanitize("Comedy"), "Comedy");
+    }
+
+    #[test]
+    fn test_strategy_transliterate() {
+        let strategy = FilenameStrategy::Transliterate;
+        assert_eq!(strategy.sanitize("Café"), "Cafe");
+        assert_eq!(strategy.sanitize("Москва"), "Moskva");
+        assert_eq!(strategy.sanitize("Kids & Family"), "Kids__Family");
+    }
+
+    #[test]
+    fn test_strategy_slug() {
+        let strategy = FilenameStrategy::Slug;
+        assert_eq!(strategy.sanitize("Kids & Family"), "kids-family");
+        assert_eq!(strategy.sanitize("  UK | Sports HD "), "uk-sports-hd");
+        assert_eq!(strategy.sanitize("Música"), "musica");
+    }
+
+    #[test]
+    fn test_file_stem_hashed_fallback() {
+        let stem = FilenameStrategy::Ascii.file_stem("北京");
+        assert!(stem.starts_with("group-"));
+        assert_eq!(stem.len(), "group-".len() + 16);
+        assert_eq!(stem, FilenameStrategy::Ascii.file_stem("北京"));
+        assert_ne!(stem, FilenameStrategy::Ascii.file_stem("上海"));
+        assert_eq!(FilenameStrategy::Ascii.file_stem("Sports"), "Sports");
+    }
+
+    #[test]
+    fn test_strategy_from_str() {
+        assert_eq!("slug".parse(), Ok(FilenameStrategy::Slug));
+        assert_eq!("Unicode".parse(), Ok(FilenameStrategy::Unicode));
+        assert!("emoji".parse::<FilenameStrategy>().is_err());
+    }
+}
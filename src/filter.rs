@@ -0,0 +1,434 @@
+//! Include/exclude filters on group names and conditions on entries.
+
+use crate::group::GroupKey;
+use crate::playlist::Entry;
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+use std::str::FromStr;
+
+/// Error returned when a pattern cannot be compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern: {}", self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// A pattern matched against a whole group name.
+///
+/// `re:` starts a regular expression and `glob:` a glob. Without a prefix,
+/// a pattern containing `*` or `?` is a glob and anything else is an exact
+/// name. Globs support `*`, `?` and character classes such as `[A-C]` or
+/// `[!0-9]`. Regular expressions must match the whole name.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self, PatternError> {
+        let regex = if let Some(regex) = pattern.strip_prefix("re:") {
+            format!("^(?:{})$", regex)
+        } else if let Some(glob) = pattern.strip_prefix("glob:") {
+            glob_to_regex(glob)?
+        } else if pattern.contains(['*', '?']) {
+            glob_to_regex(pattern)?
+        } else {
+            format!("^{}$", regex::escape(pattern))
+        };
+        let regex = RegexBuilder::new(&regex)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| PatternError(format!("{:?}: {}", pattern, e)))?;
+        Ok(Pattern {
+            source: pattern.to_string(),
+            regex,
+        })
+    }
+
+    /// Whether the pattern matches `name` in its entirety.
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+
+    /// Whether the pattern matches the whole key, as in `UK / Sports`, or
+    /// any one of its levels.
+    pub fn matches_key(&self, key: &GroupKey) -> bool {
+        key.components().iter().any(|c| self.is_match(c))
+            || (key.depth() > 1 && self.is_match(&key.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether this is a `re:` regular expression.
+    pub fn is_regex(&self) -> bool {
+        self.source.starts_with("re:")
+    }
+
+    pub(crate) fn regex(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> Result<String, PatternError> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') if !class.is_empty() && class != "!" => break,
+                        Some(c) => class.push(c),
+                        None => return Err(PatternError(format!("unclosed [ in glob {:?}", glob))),
+                    }
+                }
+                regex.push('[');
+                let class = match class.strip_prefix('!') {
+                    Some(negated) => {
+                        regex.push('^');
+                        negated.to_string()
+                    }
+                    None => class,
+                };
+                for c in class.chars() {
+                    if matches!(c, '\\' | '[' | ']' | '^' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+/// Decides which groups are written.
+///
+/// With no include patterns every group is included. A group matching any
+/// exclude pattern is left out even if it is also included.
+#[derive(Debug, Clone, Default)]
+pub struct GroupFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl GroupFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pattern for groups to keep.
+    pub fn include(mut self, pattern: Pattern) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    /// Adds a pattern for groups to leave out.
+    pub fn exclude(mut self, pattern: Pattern) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Whether no patterns were added, so every group is kept.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, key: &GroupKey) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_key(key)))
+            && !self.exclude.iter().any(|p| p.matches_key(key))
+    }
+}
+
+/// Error returned when a condition cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionError(String);
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid condition: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConditionError {}
+
+#[derive(Debug, Clone)]
+enum Field {
+    Title,
+    Url,
+    Attribute(String),
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Present,
+    Missing,
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+/// A test on one entry, such as `title!~(?i)\b4K\b`.
+///
+/// The field is `title`, `url` or the name of an `#EXTINF` attribute. The
+/// forms are:
+///
+/// - `field~regex` and `field!~regex`: the value contains a match, or not
+/// - `field=value` and `field!=value`: the value is exactly `value`, or not
+/// - `field` and `!field`: the value is present and not blank, or not
+///
+/// A missing attribute never matches `~` or `=`, and always matches `!~`
+/// and `!=`.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    field: Field,
+    test: Test,
+}
+
+impl Condition {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let value = match &self.field {
+            Field::Title => Some(entry.title.as_str()),
+            Field::Url => Some(entry.url.as_str()),
+            Field::Attribute(name) => entry.attributes.get(name),
+        };
+        match &self.test {
+            Test::Present => value.is_some_and(|v| !v.trim().is_empty()),
+            Test::Missing => value.is_none_or(|v| v.trim().is_empty()),
+            Test::Equals(expected) => value == Some(expected.as_str()),
+            Test::NotEquals(expected) => value != Some(expected.as_str()),
+            Test::Matches(regex) => value.is_some_and(|v| regex.is_match(v)),
+            Test::NotMatches(regex) => !value.is_some_and(|v| regex.is_match(v)),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let operator = s
+            .char_indices()
+            .find(|&(_, c)| c == '~' || c == '=')
+            .map(|(i, c)| match s[..i].strip_suffix('!') {
+                Some(field) => (field, c, false, &s[i + 1..]),
+                None => (&s[..i], c, true, &s[i + 1..]),
+            });
+
+        let (field, test) = match operator {
+            None => match s.trim().strip_prefix('!') {
+                Some(field) => (field, Test::Missing),
+                None => (s, Test::Present),
+            },
+            Some((field, '=', positive, value)) => {
+                let value = value.to_string();
+                if positive {
+                    (field, Test::Equals(value))
+                } else {
+                    (field, Test::NotEquals(value))
+                }
+            }
+            Some((field, _, positive, regex)) => {
+                let regex =
+                    Regex::new(regex).map_err(|e| ConditionError(format!("{:?}: {}", s, e)))?;
+                if positive {
+                    (field, Test::Matches(regex))
+                } else {
+                    (field, Test::NotMatches(regex))
+                }
+            }
+        };
+
+        let field = match field.trim() {
+            "" => {
+                return Err(ConditionError(format!(
+                    "{:?} has no field; expected e.g. title~REGEX, url!~REGEX or tvg-id",
+                    s
+                )))
+            }
+            "title" => Field::Title,
+            "url" => Field::Url,
+            name => Field::Attribute(name.to_string()),
+        };
+        Ok(Condition {
+            source: s.to_string(),
+            field,
+            test,
+        })
+    }
+}
+
+/// Decides which entries are kept: those matching every condition.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    conditions: Vec<Condition>,
+}
+
+impl EntryFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a condition every kept entry must meet.
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        self.conditions.iter().all(|c| c.matches(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(pattern: &str) -> Pattern {
+        Pattern::new(pattern, false).unwrap()
+    }
+
+    #[test]
+    fn test_exact_pattern() {
+        let news = pattern("News");
+        assert!(news.is_match("News"));
+        assert!(!news.is_match("news"));
+        assert!(!news.is_match("News HD"));
+        // Regex metacharacters in exact names are literal
+        assert!(pattern("[VIP] Sports (HD)").is_match("[VIP] Sports (HD)"));
+        assert!(Pattern::new("News", true).unwrap().is_match("NEWS"));
+    }
+
+    #[test]
+    fn test_glob_pattern() {
+        let uk = pattern("UK*");
+        assert!(uk.is_match("UK Sports"));
+        assert!(!uk.is_match("The UK"));
+        assert!(pattern("Sport?").is_match("Sports"));
+        assert!(pattern("glob:[A-C]*").is_match("Cinema"));
+        assert!(!pattern("glob:[!A-C]*").is_match("Cinema"));
+        assert!(pattern("*.tv").is_match("news.tv"));
+        assert!(!pattern("*.tv").is_match("newsxtv"));
+        assert!(Pattern::new("glob:[a-c", false).is_err());
+    }
+
+    #[test]
+    fn test_regex_pattern() {
+        let regex = pattern("re:(Sports|News)( HD)?");
+        assert!(regex.is_match("Sports HD"));
+        assert!(!regex.is_match("Sports HD+"));
+        assert!(Pattern::new("re:sports", true).unwrap().is_match("Sports"));
+        let err = Pattern::new("re:(open", false).unwrap_err();
+        assert!(err.to_string().contains("re:(open"));
+    }
+
+    #[test]
+    fn test_group_filter() {
+        let filter = GroupFilter::new()
+            .include(pattern("UK*"))
+            .include(pattern("News"))
+            .exclude(pattern("re:.*Adult.*"));
+
+        assert!(filter.matches(&GroupKey::from("UK Sports")));
+        assert!(filter.matches(&GroupKey::from("News")));
+        assert!(!filter.matches(&GroupKey::from("Music")));
+        assert!(!filter.matches(&GroupKey::from("UK Adult")));
+
+        // Nested keys match on any level or on the whole key
+        let nested = GroupKey::new(vec!["FR".to_string(), "News".to_string()]);
+        assert!(filter.matches(&nested));
+        assert!(GroupFilter::new()
+            .include(pattern("FR / *"))
+            .matches(&nested));
+        assert!(GroupFilter::new().matches(&GroupKey::from("Anything")));
+    }
+
+    fn entry(extinf_line: &str, url: &str) -> Entry {
+        Entry::new(extinf_line.to_string(), url.to_string())
+    }
+
+    fn condition(condition: &str) -> Condition {
+        condition.parse().unwrap()
+    }
+
+    #[test]
+    fn test_condition_forms() {
+        let uhd = entry(
+            r#"#EXTINF:-1 tvg-id="sky.uk" group-title="Sports",Sky Sports UHD"#,
+            "http://bad.example.com/sky",
+        );
+        let plain = entry(
+            r#"#EXTINF:-1 tvg-id="" group-title="News",BBC News"#,
+            "http://good.example.com/bbc",
+        );
+
+        let not_4k = condition(r"title!~(?i)\b(4K|UHD)\b");
+        assert!(!not_4k.matches(&uhd));
+        assert!(not_4k.matches(&plain));
+
+        let bad_host = condition(r"url~^https?://bad\.example\.com/");
+        assert!(bad_host.matches(&uhd));
+        assert!(!bad_host.matches(&plain));
+
+        // A blank attribute counts as missing
+        assert!(condition("tvg-id").matches(&uhd));
+        assert!(!condition("tvg-id").matches(&plain));
+        assert!(condition("!tvg-id").matches(&plain));
+
+        assert!(condition("group-title=News").matches(&plain));
+        assert!(condition("group-title!=News").matches(&uhd));
+        assert!(!condition("tvg-logo~.").matches(&plain));
+        assert!(condition("tvg-logo!~.").matches(&plain));
+    }
+
+    #[test]
+    fn test_condition_parse_errors() {
+        assert!("~foo".parse::<Condition>().is_err());
+        assert!("!".parse::<Condition>().is_err());
+        let err = "title~(open".parse::<Condition>().unwrap_err();
+        assert!(err.to_string().contains("title~(open"));
+    }
+
+    #[test]
+    fn test_entry_filter_requires_every_condition() {
+        let filter = EntryFilter::new()
+            .with_condition(condition("tvg-id"))
+            .with_condition(condition("title!~HD"));
+        assert!(filter.matches(&entry(r#"#EXTINF:-1 tvg-id="a",News"#, "http://a")));
+        assert!(!filter.matches(&entry(r#"#EXTINF:-1 tvg-id="a",News HD"#, "http://a")));
+        assert!(!filter.matches(&entry("#EXTINF:-1,News", "http://a")));
+        assert!(EntryFilter::new().matches(&entry("#EXTINF:-1,News", "http://a")));
+    }
+}
@@ -0,0 +1,825 @@
+//! Grouping of entries into output buckets.
+
+use crate::playlist::{split_location, Entry, DEFAULT_GROUP, EXTGRP};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// Identifies one output group: a single name for a flat split, or one
+/// name per level for a nested split such as `UK / Sports`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GroupKey(Vec<String>);
+
+impl GroupKey {
+    /// Creates a key from its components, outermost level first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `components` is empty.
+    pub fn new(components: Vec<String>) -> Self {
+        assert!(!components.is_empty(), "a group key needs a component");
+        GroupKey(components)
+    }
+
+    pub fn components(&self) -> &[String] {
+        &self.0
+    }
+
+    /// The innermost component, i.e. the group's own name.
+    pub fn name(&self) -> &str {
+        self.0.last().expect("GroupKey is never empty")
+    }
+
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Keys of the enclosing levels, outermost first: `UK / Sports / HD`
+    /// yields `UK` and `UK / Sports`.
+    pub fn ancestors(&self) -> impl Iterator<Item = GroupKey> + '_ {
+        (1..self.0.len()).map(|len| GroupKey(self.0[..len].to_vec()))
+    }
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(" / "))
+    }
+}
+
+impl From<&str> for GroupKey {
+    fn from(name: &str) -> Self {
+        GroupKey(vec![name.to_string()])
+    }
+}
+
+impl From<String> for GroupKey {
+    fn from(name: String) -> Self {
+        GroupKey(vec![name])
+    }
+}
+
+/// Entries bucketed by group.
+pub type Groups = HashMap<GroupKey, Vec<Entry>>;
+
+/// Selects the `#EXTINF` attributes that decide which group an entry
+/// belongs to. Several attributes produce nested groups, outermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupBy {
+    attributes: Vec<String>,
+    fallback: String,
+    delimiter: Option<String>,
+}
+
+impl GroupBy {
+    /// Groups by `attribute`; entries where it is missing or blank go to
+    /// the `fallback` group.
+    pub fn new(attribute: impl Into<String>, fallback: impl Into<String>) -> Self {
+        GroupBy {
+            attributes: vec![attribute.into()],
+            fallback: fallback.into(),
+            delimiter: None,
+        }
+    }
+
+    /// Adds a nested level grouped by `attribute`, e.g. `tvg-country` then
+    /// `group-title` for `UK / Sports`. The fallback applies to every level.
+    pub fn then_by(mut self, attribute: impl Into<String>) -> Self {
+        self.attributes.push(attribute.into());
+        self
+    }
+
+    /// Treats the attributes as multi-valued: each value is split on
+    /// `delimiter` and the entry goes into every group listed, e.g.
+    /// `group-title="Sports;HD"` with `";"`.
+    pub fn with_delimiter(mut self, delimiter: impl Into<String>) -> Self {
+        self.delimiter = Some(delimiter.into()).filter(|d| !d.is_empty());
+        self
+    }
+
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
+    }
+
+    pub fn fallback(&self) -> &str {
+        &self.fallback
+    }
+
+    pub fn delimiter(&self) -> Option<&str> {
+        self.delimiter.as_deref()
+    }
+
+    /// Returns the groups `entry` belongs to, in the order they are listed
+    /// in the attributes. Never empty.
+    pub fn keys(&self, entry: &Entry) -> Vec<GroupKey> {
+        let mut keys = vec![Vec::with_capacity(self.attributes.len())];
+        for attribute in &self.attributes {
+            let values = self.values(entry.attributes.get(attribute).unwrap_or_default());
+            keys = keys
+                .into_iter()
+                .flat_map(|prefix: Vec<String>| {
+                    values.iter().map(move |value| {
+                        let mut key = prefix.clone();
+                        key.push(value.clone());
+                        key
+                    })
+                })
+                .collect();
+        }
+        keys.into_iter().map(GroupKey).collect()
+    }
+
+    /// Splits one attribute value into group names, dropping blanks and
+    /// repeats, or returns the fallback if nothing is left.
+    fn values(&self, value: &str) -> Vec<String> {
+        let values: Vec<&str> = match &self.delimiter {
+            Some(delimiter) => value.split(delimiter.as_str()).collect(),
+            None => vec![value],
+        };
+
+        let mut names: Vec<String> = Vec::with_capacity(values.len());
+        for value in values.into_iter().map(str::trim) {
+            if !value.is_empty() && !names.iter().any(|n| n == value) {
+                names.push(value.to_string());
+            }
+        }
+        if names.is_empty() {
+            names.push(self.fallback.clone());
+        }
+        names
+    }
+}
+
+impl Default for GroupBy {
+    /// Groups by `group-title`, with [`DEFAULT_GROUP`] as the fallback.
+    fn default() -> Self {
+        GroupBy::new("group-title", DEFAULT_GROUP)
+    }
+}
+
+/// Buckets entries by the groups `group_by` assigns them. An entry that
+/// belongs to several groups is cloned into each of them.
+pub fn group_entries<I: IntoIterator<Item = Entry>>(entries: I, group_by: &GroupBy) -> Groups {
+    let mut groups: Groups = HashMap::new();
+    for entry in entries {
+        let mut keys = group_by.keys(&entry);
+        let last = keys.pop().expect("GroupBy::keys is never empty");
+        for key in keys {
+            groups.entry(key).or_default().push(entry.clone());
+        }
+        groups.entry(last).or_default().push(entry);
+    }
+    groups
+}
+
+/// Order in which groups are listed and written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupSort {
+    /// Order of first appearance in the input.
+    #[default]
+    FirstSeen,
+    /// Group name, case-insensitively, outermost level first.
+    Name,
+    /// Number of channels in the group.
+    Count,
+}
+
+impl GroupSort {
+    pub const ALL: [GroupSort; 3] = [GroupSort::FirstSeen, GroupSort::Name, GroupSort::Count];
+}
+
+impl fmt::Display for GroupSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GroupSort::FirstSeen => "first-seen",
+            GroupSort::Name => "name",
+            GroupSort::Count => "count",
+        })
+    }
+}
+
+impl FromStr for GroupSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GroupSort::ALL
+            .into_iter()
+            .find(|sort| sort.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown sort order {:?}; expected name, count or first-seen",
+                    s
+                )
+            })
+    }
+}
+
+/// Where the group of an entry is read from when `group-title` and an
+/// `#EXTGRP` line disagree or one of them is missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupSource {
+    /// The `group-title` attribute, or `#EXTGRP` without one.
+    #[default]
+    GroupTitle,
+    /// The `#EXTGRP` line, or `group-title` without one.
+    Extgrp,
+}
+
+impl GroupSource {
+    pub const ALL: [GroupSource; 2] = [GroupSource::GroupTitle, GroupSource::Extgrp];
+
+    /// Sets the group of `entry`, its `group_name` and `group-title`
+    /// attribute, from the preferred source. A `group-title` that loses to
+    /// the `#EXTGRP` line is rewritten in the `#EXTINF` line as well, so
+    /// that the entry is written with the group it was put in; a group
+    /// found on the `#EXTGRP` line alone is written by that line.
+    pub fn apply(&self, entry: &mut Entry) {
+        let title = entry
+            .attributes
+            .get("group-title")
+            .map(str::trim)
+            .filter(|title| !title.is_empty());
+        let extgrp = entry.extgrp();
+        let group = match self {
+            GroupSource::GroupTitle => title.or(extgrp),
+            GroupSource::Extgrp => extgrp.or(title),
+        };
+        let Some(group) = group.filter(|group| Some(*group) != title) else {
+            return;
+        };
+        let group = group.to_string();
+        if title.is_some() {
+            entry.set_attribute("group-title", &group);
+        } else {
+            entry.group_name = group.clone();
+            entry.attributes.insert("group-title", group);
+        }
+    }
+}
+
+impl fmt::Display for GroupSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GroupSource::GroupTitle => "group-title",
+            GroupSource::Extgrp => "extgrp",
+        })
+    }
+}
+
+impl FromStr for GroupSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GroupSource::ALL
+            .into_iter()
+            .find(|source| source.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown group source {:?}; expected group-title or extgrp",
+                    s
+                )
+            })
+    }
+}
+
+/// How the group of an entry is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupOutput {
+    /// The lines are written as read.
+    #[default]
+    AsIs,
+    /// As a `group-title` attribute only, without `#EXTGRP` lines.
+    GroupTitle,
+    /// As an `#EXTGRP` line only, without `group-title` attribute.
+    Extgrp,
+    /// As both a `group-title` attribute and an `#EXTGRP` line.
+    Both,
+}
+
+impl GroupOutput {
+    pub const ALL: [GroupOutput; 4] = [
+        GroupOutput::AsIs,
+        GroupOutput::GroupTitle,
+        GroupOutput::Extgrp,
+        GroupOutput::Both,
+    ];
+
+    /// Rewrites the lines of `entry` to carry its group in this form. An
+    /// entry without a group is left alone.
+    pub fn apply(&self, entry: &mut Entry) {
+        let group = match entry.attributes.get("group-title").map(str::trim) {
+            Some(group) if !group.is_empty() && *self != GroupOutput::AsIs => group.to_string(),
+            _ => return,
+        };
+
+        entry.comments.retain(|line| !line.starts_with(EXTGRP));
+        entry.directives.retain(|line| !line.starts_with(EXTGRP));
+        if matches!(self, GroupOutput::Extgrp | GroupOutput::Both) {
+            entry.directives.insert(0, format!("{}{}", EXTGRP, group));
+        }
+        if *self == GroupOutput::Extgrp {
+            entry.remove_attribute("group-title");
+            // Still the entry's group, only no longer written as such
+            entry.group_name = group;
+        } else {
+            entry.set_attribute("group-title", &group);
+        }
+    }
+}
+
+impl fmt::Display for GroupOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GroupOutput::AsIs => "as-is",
+            GroupOutput::GroupTitle => "group-title",
+            GroupOutput::Extgrp => "extgrp",
+            GroupOutput::Both => "both",
+        })
+    }
+}
+
+impl FromStr for GroupOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GroupOutput::ALL
+            .into_iter()
+            .find(|output| output.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown group output {:?}; expected as-is, group-title, extgrp or both",
+                    s
+                )
+            })
+    }
+}
+
+/// The group of a plain entry, which has no `#EXTINF` line and hence no
+/// `group-title`, derived from its location.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlainGroup {
+    /// The fallback group.
+    #[default]
+    Fallback,
+    /// The host of a URL, such as `example.com`.
+    Host,
+    /// The name of the directory holding the file, such as `Album` for
+    /// `Music/Album/01.mp3`.
+    Directory,
+    /// The file extension, such as `mp3`.
+    Extension,
+}
+
+impl PlainGroup {
+    pub const ALL: [PlainGroup; 4] = [
+        PlainGroup::Fallback,
+        PlainGroup::Host,
+        PlainGroup::Directory,
+        PlainGroup::Extension,
+    ];
+
+    /// The group derived from `location`, if it has one.
+    pub fn derive(&self, location: &str) -> Option<String> {
+        let (host, path) = split_location(location);
+        let mut segments = path.rsplit(['/', '\\']);
+        let name = segments.next().unwrap_or_default();
+        let group = match self {
+            PlainGroup::Fallback => None,
+            PlainGroup::Host => host.map(str::to_ascii_lowercase),
+            PlainGroup::Directory => segments.next().map(str::to_string),
+            PlainGroup::Extension => name
+                .rsplit_once('.')
+                .filter(|(stem, _)| !stem.is_empty())
+                .map(|(_, extension)| extension.to_ascii_lowercase()),
+        };
+        group.filter(|group| !group.trim().is_empty())
+    }
+
+    /// Puts a plain entry without group into the group derived from its
+    /// URL, setting its `group_name` and `group-title` attribute. The
+    /// entry is written without `#EXTINF` line all the same.
+    pub fn apply(&self, entry: &mut Entry) {
+        if !entry.is_plain() || entry.attributes.contains_key("group-title") {
+            return;
+        }
+        if let Some(group) = self.derive(&entry.url) {
+            entry.group_name = group.clone();
+            entry.attributes.insert("group-title", group);
+        }
+    }
+}
+
+impl fmt::Display for PlainGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlainGroup::Fallback => "fallback",
+            PlainGroup::Host => "host",
+            PlainGroup::Directory => "directory",
+            PlainGroup::Extension => "extension",
+        })
+    }
+}
+
+impl FromStr for PlainGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PlainGroup::ALL
+            .into_iter()
+            .find(|group| group.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown plain group {:?}; expected fallback, host, directory or extension",
+                    s
+                )
+            })
+    }
+}
+
+/// Per-group channel counts gathered by [`count_groups`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    /// Number of entries in each group.
+    pub counts: HashMap<GroupKey, usize>,
+    /// Groups in order of first appearance, or as arranged by
+    /// [`GroupStats::sort`].
+    pub order: Vec<GroupKey>,
+    /// 1-based position of every group in order of first appearance, kept
+    /// as it is by [`GroupStats::sort`] and [`GroupStats::retain`].
+    pub first_seen: HashMap<GroupKey, usize>,
+    /// Number of entries read.
+    pub entries: usize,
+    /// Number of entries that landed in more than one group.
+    pub multi_group_entries: usize,
+}
+
+impl GroupStats {
+    /// Records one entry belonging to `keys`.
+    pub fn record(&mut self, keys: &[GroupKey]) {
+        self.entries += 1;
+        if keys.len() > 1 {
+            self.multi_group_entries += 1;
+        }
+        for key in keys {
+            let count = self.counts.entry(key.clone()).or_insert(0);
+            if *count == 0 {
+                self.order.push(key.clone());
+                self.first_seen
+                    .insert(key.clone(), self.first_seen.len() + 1);
+            }
+            *count += 1;
+        }
+    }
+
+    /// Reorders [`GroupStats::order`], which is in order of first
+    /// appearance until sorted. Ties keep their current order, also when
+    /// `descending`.
+    pub fn sort(&mut self, sort: GroupSort, descending: bool) {
+        let counts = &self.counts;
+        let compare = |a: &GroupKey, b: &GroupKey| match sort {
+            GroupSort::FirstSeen => Ordering::Equal,
+            GroupSort::Name => compare_names(a, b),
+            GroupSort::Count => counts[a].cmp(&counts[b]),
+        };
+        if sort == GroupSort::FirstSeen {
+            if descending {
+                self.order.reverse();
+            }
+        } else if descending {
+            self.order.sort_by(|a, b| compare(b, a));
+        } else {
+            self.order.sort_by(compare);
+        }
+    }
+
+    /// Removes the groups for which `keep` returns false, returning them
+    /// with their channel counts in their current order.
+    pub fn retain<F>(&mut self, mut keep: F) -> Vec<(GroupKey, usize)>
+    where
+        F: FnMut(&GroupKey) -> bool,
+    {
+        let mut removed = Vec::new();
+        let counts = &mut self.counts;
+        self.order.retain(|key| {
+            if keep(key) {
+                return true;
+            }
+            let count = counts.remove(key).unwrap_or_default();
+            removed.push((key.clone(), count));
+            false
+        });
+        removed
+    }
+
+    /// The groups in [`GroupStats::order`], in order of first appearance
+    /// regardless of sorting.
+    pub fn first_seen_order(&self) -> Vec<&GroupKey> {
+        let mut order: Vec<&GroupKey> = self.order.iter().collect();
+        order.sort_by_key(|key| self.first_seen[*key]);
+        order
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+}
+
+fn compare_names(a: &GroupKey, b: &GroupKey) -> Ordering {
+    let lowercase = |key: &GroupKey| -> Vec<String> {
+        key.components().iter().map(|c| c.to_lowercase()).collect()
+    };
+    lowercase(a).cmp(&lowercase(b)).then_with(|| a.cmp(b))
+}
+
+/// Counts entries per group without keeping the entries themselves.
+pub fn count_groups<I>(entries: I, group_by: &GroupBy) -> io::Result<GroupStats>
+where
+    I: IntoIterator<Item = io::Result<Entry>>,
+{
+    let mut stats = GroupStats::default();
+    for entry in entries {
+        stats.record(&group_by.keys(&entry?));
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{entries, parse_str};
+
+    fn key(name: &str) -> GroupKey {
+        GroupKey::from(name)
+    }
+
+    fn nested(components: &[&str]) -> GroupKey {
+        GroupKey::new(components.iter().map(|c| c.to_string()).collect())
+    }
+
+    #[test]
+    fn test_group_entries() {
+        let playlist = parse_str(
+            r#"#EXTM3U
+#EXTINF:-1 group-title="Sports",Sports 1
+http://example.com/sports1
+#EXTINF:-1 group-title="News",News 1
+http://example.com/news1
+#EXTINF:-1 group-title="Sports",Sports 2
+http://example.com/sports2
+"#,
+        );
+
+        let groups = group_entries(playlist, &GroupBy::default());
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[&key("Sports")].len(), 2);
+        assert_eq!(groups[&key("Sports")][1].title, "Sports 2");
+        assert_eq!(groups[&key("News")].len(), 1);
+    }
+
+    #[test]
+    fn test_count_groups() {
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports",Sports 1
+http://example.com/sports1
+#EXTINF:-1,No Group
+http://example.com/other
+#EXTINF:-1 group-title="Sports",Sports 2
+http://example.com/sports2
+"#;
+
+        let stats = count_groups(entries(content.as_bytes()), &GroupBy::default()).unwrap();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.counts.len(), 2);
+        assert_eq!(stats.counts[&key("Sports")], 2);
+        assert_eq!(stats.counts[&key("Unknown")], 1);
+        assert_eq!(stats.multi_group_entries, 0);
+        assert_eq!(stats.order, vec![key("Sports"), key("Unknown")]);
+    }
+
+    #[test]
+    fn test_group_stats_sort() {
+        let sorted = |sort: GroupSort, descending: bool| {
+            let mut stats = GroupStats::default();
+            for name in ["sports", "News", "Sports", "Kids", "News", "Kids"] {
+                stats.record(&[key(name)]);
+            }
+            stats.sort(sort, descending);
+            stats.order
+        };
+
+        assert_eq!(
+            sorted(GroupSort::Name, false),
+            vec![key("Kids"), key("News"), key("Sports"), key("sports")]
+        );
+        assert_eq!(
+            sorted(GroupSort::FirstSeen, true),
+            vec![key("Kids"), key("Sports"), key("News"), key("sports")]
+        );
+        // Ties on count keep their order of first appearance either way
+        assert_eq!(
+            sorted(GroupSort::Count, true),
+            vec![key("News"), key("Kids"), key("sports"), key("Sports")]
+        );
+        assert_eq!(
+            sorted(GroupSort::Count, false),
+            vec![key("sports"), key("Sports"), key("News"), key("Kids")]
+        );
+    }
+
+    #[test]
+    fn test_group_stats_retain() {
+        let mut stats = GroupStats::default();
+        for name in ["Sports", "News", "Music", "News"] {
+            stats.record(&[key(name)]);
+        }
+
+        let removed = stats.retain(|key| key.name() != "News" && key.name() != "Music");
+        assert_eq!(removed, vec![(key("News"), 2), (key("Music"), 1)]);
+        assert_eq!(stats.order, vec![key("Sports")]);
+        assert_eq!(stats.counts.len(), 1);
+    }
+
+    #[test]
+    fn test_group_by_attribute_with_fallback() {
+        let playlist = parse_str(
+            r#"#EXTM3U
+#EXTINF:-1 tvg-country="UK" group-title="News",BBC News
+http://example.com/bbc
+#EXTINF:-1 tvg-country="FR" group-title="News",France 24
+http://example.com/f24
+#EXTINF:-1 tvg-country="" group-title="Sports",No Country
+http://example.com/blank
+#EXTINF:-1 group-title="Sports",Missing Country
+http://example.com/missing
+"#,
+        );
+
+        let groups = group_entries(playlist, &GroupBy::new("tvg-country", "Other"));
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&key("UK")].len(), 1);
+        assert_eq!(groups[&key("FR")].len(), 1);
+        assert_eq!(groups[&key("Other")].len(), 2);
+    }
+
+    #[test]
+    fn test_group_by_multi_valued_attribute() {
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports;HD;Europe",Eurosport HD
+http://example.com/eurosport
+#EXTINF:-1 group-title="Sports; HD",Sky Sports HD
+http://example.com/sky
+#EXTINF:-1 group-title="News",BBC News
+http://example.com/bbc
+#EXTINF:-1 group-title=";;",Only Delimiters
+http://example.com/empty
+"#;
+        let group_by = GroupBy::default().with_delimiter(";");
+
+        let stats = count_groups(entries(content.as_bytes()), &group_by).unwrap();
+        assert_eq!(stats.entries, 4);
+        assert_eq!(stats.multi_group_entries, 2);
+        assert_eq!(stats.counts[&key("Sports")], 2);
+        assert_eq!(stats.counts[&key("HD")], 2);
+        assert_eq!(stats.counts[&key("Europe")], 1);
+        assert_eq!(stats.counts[&key("News")], 1);
+        assert_eq!(stats.counts[&key("Unknown")], 1);
+
+        let groups = group_entries(parse_str(content), &group_by);
+        assert_eq!(groups[&key("HD")][0].title, "Eurosport HD");
+        assert_eq!(groups[&key("HD")][1].title, "Sky Sports HD");
+    }
+
+    #[test]
+    fn test_group_by_without_delimiter_keeps_literal_value() {
+        let playlist = parse_str(
+            r#"#EXTM3U
+#EXTINF:-1 group-title="Sports;HD",Eurosport HD
+http://example.com/eurosport
+"#,
+        );
+        let groups = group_entries(playlist, &GroupBy::default());
+        assert_eq!(groups.len(), 1);
+        assert!(groups.contains_key(&key("Sports;HD")));
+    }
+
+    #[test]
+    fn test_group_by_nested_attributes() {
+        let content = r#"#EXTM3U
+#EXTINF:-1 tvg-country="UK" group-title="News",BBC News
+http://example.com/bbc
+#EXTINF:-1 tvg-country="UK" group-title="Sports;HD",Sky Sports HD
+http://example.com/sky
+#EXTINF:-1 group-title="News",No Country
+http://example.com/other
+"#;
+        let group_by = GroupBy::new("tvg-country", "Unknown")
+            .then_by("group-title")
+            .with_delimiter(";");
+
+        let groups = group_entries(parse_str(content), &group_by);
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[&nested(&["UK", "News"])][0].title, "BBC News");
+        assert_eq!(groups[&nested(&["UK", "Sports"])].len(), 1);
+        assert_eq!(groups[&nested(&["UK", "HD"])].len(), 1);
+        assert_eq!(groups[&nested(&["Unknown", "News"])].len(), 1);
+    }
+
+    fn extgrp_entry(extinf: &str, extgrp: &str) -> Entry {
+        let mut entry = Entry::new(extinf.to_string(), "http://example.com/a".to_string());
+        entry.directives.push(format!("#EXTGRP:{}", extgrp));
+        entry
+    }
+
+    #[test]
+    fn test_group_source() {
+        let mut entry = extgrp_entry("#EXTINF:-1,No Title Group", "Movies");
+        assert_eq!(entry.group_name, DEFAULT_GROUP);
+        GroupSource::GroupTitle.apply(&mut entry);
+        assert_eq!(entry.group_name, "Movies");
+        assert_eq!(entry.attributes.get("group-title"), Some("Movies"));
+        assert_eq!(entry.extinf_line, "#EXTINF:-1,No Title Group");
+
+        let line = r#"#EXTINF:-1 group-title="News",Both"#;
+        let mut entry = extgrp_entry(line, "Movies");
+        GroupSource::GroupTitle.apply(&mut entry);
+        assert_eq!(entry.group_name, "News");
+        let mut entry = extgrp_entry(line, "Movies");
+        GroupSource::Extgrp.apply(&mut entry);
+        assert_eq!(entry.group_name, "Movies");
+        // The written group-title agrees with the group
+        assert_eq!(entry.extinf_line, r#"#EXTINF:-1 group-title="Movies",Both"#);
+
+        assert_eq!("EXTGRP".parse::<GroupSource>(), Ok(GroupSource::Extgrp));
+        assert!("extinf".parse::<GroupSource>().is_err());
+    }
+
+    #[test]
+    fn test_group_output() {
+        let line = r#"#EXTINF:-1 group-title="News",Both"#;
+        let mut entry = extgrp_entry(line, "Old");
+        GroupOutput::GroupTitle.apply(&mut entry);
+        assert!(entry.directives.is_empty());
+        assert_eq!(entry.extinf_line, line);
+
+        let mut entry = extgrp_entry(line, "Old");
+        GroupOutput::Extgrp.apply(&mut entry);
+        assert_eq!(entry.directives, vec!["#EXTGRP:News"]);
+        assert_eq!(entry.extinf_line, "#EXTINF:-1,Both");
+        assert_eq!(entry.group_name, "News");
+
+        let mut entry = extgrp_entry("#EXTINF:-1,Only", "Movies");
+        GroupSource::GroupTitle.apply(&mut entry);
+        GroupOutput::Both.apply(&mut entry);
+        assert_eq!(entry.directives, vec!["#EXTGRP:Movies"]);
+        assert_eq!(entry.extinf_line, r#"#EXTINF:-1 group-title="Movies",Only"#);
+
+        // Nothing to write for an entry without a group
+        let mut entry = Entry::new("#EXTINF:-1,None".to_string(), String::new());
+        GroupOutput::Both.apply(&mut entry);
+        assert!(entry.directives.is_empty());
+        assert_eq!(entry.extinf_line, "#EXTINF:-1,None");
+
+        assert_eq!("as-is".parse::<GroupOutput>(), Ok(GroupOutput::AsIs));
+        assert!("neither".parse::<GroupOutput>().is_err());
+    }
+
+    #[test]
+    fn test_plain_group() {
+        let url = "http://Example.com:8080/music/Album/01 Intro.MP3?token=1";
+        assert_eq!(PlainGroup::Fallback.derive(url), None);
+        assert_eq!(PlainGroup::Host.derive(url).as_deref(), Some("example.com"));
+        assert_eq!(PlainGroup::Directory.derive(url).as_deref(), Some("Album"));
+        assert_eq!(PlainGroup::Extension.derive(url).as_deref(), Some("mp3"));
+
+        let path = "C:\\Music\\Album\\.hidden";
+        assert_eq!(PlainGroup::Host.derive(path), None);
+        assert_eq!(PlainGroup::Directory.derive(path).as_deref(), Some("Album"));
+        assert_eq!(PlainGroup::Extension.derive(path), None);
+        assert_eq!(PlainGroup::Directory.derive("song.mp3"), None);
+
+        let mut entry = Entry::plain("Rock/song.mp3".to_string());
+        PlainGroup::Directory.apply(&mut entry);
+        assert_eq!(entry.group_name, "Rock");
+        assert!(entry.is_plain());
+
+        // Entries with an #EXTINF line keep their group
+        let mut entry = Entry::new("#EXTINF:-1,A".to_string(), "Rock/a.mp3".to_string());
+        PlainGroup::Directory.apply(&mut entry);
+        assert_eq!(entry.group_name, DEFAULT_GROUP);
+
+        assert_eq!("Host".parse::<PlainGroup>(), Ok(PlainGroup::Host));
+        assert!("domain".parse::<PlainGroup>().is_err());
+    }
+
+    #[test]
+    fn test_group_key_ancestors_and_display() {
+        let key = nested(&["UK", "Sports", "HD"]);
+        let ancestors: Vec<GroupKey> = key.ancestors().collect();
+        assert_eq!(ancestors, vec![nested(&["UK"]), nested(&["UK", "Sports"])]);
+        assert_eq!(key.name(), "HD");
+        assert_eq!(key.to_string(), "UK / Sports / HD");
+        assert_eq!(GroupKey::from("News").ancestors().count(), 0);
+    }
+}
@@ -0,0 +1,713 @@
+//! HLS master and media playlists.
+//!
+//! HLS playlists are M3U files as well, but they list stream variants or
+//! media segments rather than channels. They are told apart from channel
+//! lists by their `#EXT-X-` tags, see [`detect_hls`], and split on their own
+//! terms: a master playlist by an attribute of its variants, a media
+//! playlist into chunks of a fixed duration.
+
+use crate::extinf::{parse_extinf, Attributes};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Tags that only appear in master playlists.
+const MASTER_TAGS: &[&str] = &[
+    "#EXT-X-STREAM-INF",
+    "#EXT-X-I-FRAME-STREAM-INF",
+    "#EXT-X-MEDIA",
+    "#EXT-X-SESSION-DATA",
+    "#EXT-X-SESSION-KEY",
+    "#EXT-X-CONTENT-STEERING",
+];
+
+/// Tags that only appear in media playlists.
+const MEDIA_TAGS: &[&str] = &[
+    "#EXT-X-TARGETDURATION",
+    "#EXT-X-MEDIA-SEQUENCE",
+    "#EXT-X-DISCONTINUITY-SEQUENCE",
+    "#EXT-X-PLAYLIST-TYPE",
+    "#EXT-X-I-FRAMES-ONLY",
+    "#EXT-X-ENDLIST",
+    "#EXT-X-PART-INF",
+    "#EXT-X-SERVER-CONTROL",
+    "#EXT-X-KEY",
+    "#EXT-X-MAP",
+    "#EXT-X-BYTERANGE",
+    "#EXT-X-DISCONTINUITY",
+    "#EXT-X-PROGRAM-DATE-TIME",
+    "#EXT-X-GAP",
+];
+
+/// Tags of a media playlist that apply to the whole playlist rather than
+/// to the segment after them.
+const MEDIA_PLAYLIST_TAGS: &[&str] = &[
+    "#EXT-X-VERSION",
+    "#EXT-X-INDEPENDENT-SEGMENTS",
+    "#EXT-X-START",
+    "#EXT-X-DEFINE",
+    "#EXT-X-TARGETDURATION",
+    "#EXT-X-PLAYLIST-TYPE",
+    "#EXT-X-I-FRAMES-ONLY",
+    "#EXT-X-PART-INF",
+    "#EXT-X-SERVER-CONTROL",
+];
+
+const MEDIA_SEQUENCE: &str = "#EXT-X-MEDIA-SEQUENCE";
+const DISCONTINUITY_SEQUENCE: &str = "#EXT-X-DISCONTINUITY-SEQUENCE";
+const DISCONTINUITY: &str = "#EXT-X-DISCONTINUITY";
+const ENDLIST: &str = "#EXT-X-ENDLIST";
+const KEY: &str = "#EXT-X-KEY";
+const MAP: &str = "#EXT-X-MAP";
+
+/// Variant attributes naming a group of renditions, with the `TYPE` of the
+/// `#EXT-X-MEDIA` renditions they refer to.
+const RENDITION_GROUPS: &[(&str, &str)] = &[
+    ("AUDIO", "AUDIO"),
+    ("VIDEO", "VIDEO"),
+    ("SUBTITLES", "SUBTITLES"),
+    ("CLOSED-CAPTIONS", "CLOSED-CAPTIONS"),
+];
+
+/// The two kinds of HLS playlist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HlsKind {
+    /// Lists the variants of a stream, one media playlist each.
+    Master,
+    /// Lists the media segments of one variant.
+    Media,
+}
+
+impl fmt::Display for HlsKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HlsKind::Master => "master",
+            HlsKind::Media => "media",
+        })
+    }
+}
+
+/// The name of the tag on `line`: `#EXT-X-MEDIA` for
+/// `#EXT-X-MEDIA:TYPE=AUDIO`.
+fn tag_name(line: &str) -> &str {
+    line.split(':').next().unwrap_or_default()
+}
+
+/// Whether `line` holds the tag `name`.
+fn is_tag(line: &str, name: &str) -> bool {
+    tag_name(line) == name
+}
+
+/// Tells whether `reader` holds an HLS playlist, and which kind, from the
+/// first tag specific to master or media playlists. Both have such a tag
+/// before their first URI, so reading stops at the first `#EXTINF` line or
+/// URI.
+pub fn detect_hls<R: BufRead>(reader: R) -> io::Result<Option<HlsKind>> {
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_start_matches('\u{feff}').trim();
+        let name = tag_name(line);
+        if MASTER_TAGS.contains(&name) {
+            return Ok(Some(HlsKind::Master));
+        }
+        if MEDIA_TAGS.contains(&name) {
+            return Ok(Some(HlsKind::Media));
+        }
+        if name == "#EXTINF" || !(line.is_empty() || line.starts_with('#')) {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Parses an HLS attribute list such as
+/// `BANDWIDTH=1280000,CODECS="avc1.4d401e,mp4a.40.2"`, with quotes removed
+/// from quoted values.
+pub fn parse_attribute_list(list: &str) -> Attributes {
+    let mut attributes = Attributes::new();
+    let mut rest = list.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        attributes.insert(key.trim(), value);
+        let after = after.trim_start();
+        rest = after.strip_prefix(',').unwrap_or(after).trim_start();
+    }
+    attributes
+}
+
+/// Prefixes every relative URI of a written HLS playlist with `base`, so
+/// that it still points at the same file when the playlist is moved: the
+/// URI lines as well as the `URI` attributes of tags such as `#EXT-X-MEDIA`
+/// and `#EXT-X-KEY`. `base` is a relative URI reference ending in `/`, such
+/// as `../videos/`, or empty to keep every URI as is.
+pub fn rebase_uris(playlist: &str, base: &str) -> String {
+    let rebase = |uri: &str| -> String {
+        if base.is_empty() || !is_relative_uri(uri) {
+            uri.to_string()
+        } else {
+            format!("{}{}", base, uri)
+        }
+    };
+    let mut rebased = String::with_capacity(playlist.len());
+    for line in playlist.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        if content.starts_with('#') {
+            let mut rest = content;
+            while let Some(start) = find_uri_attribute(rest) {
+                let (before, value) = rest.split_at(start);
+                let (uri, after) = value.split_once('"').unwrap_or((value, ""));
+                rebased.push_str(before);
+                rebased.push_str(&rebase(uri));
+                if value.len() > uri.len() {
+                    rebased.push('"');
+                }
+                rest = after;
+            }
+            rebased.push_str(rest);
+        } else if content.trim().is_empty() {
+            rebased.push_str(content);
+        } else {
+            rebased.push_str(&rebase(content.trim()));
+        }
+        rebased.push_str(ending);
+    }
+    rebased
+}
+
+/// The position of the value of the first `URI="..."` attribute in a tag
+/// line, not counting attributes such as `SERVER-URI`.
+fn find_uri_attribute(line: &str) -> Option<usize> {
+    const URI: &str = "URI=\"";
+    line.match_indices(URI)
+        .find(|(i, _)| line[..*i].ends_with([':', ',']))
+        .map(|(i, _)| i + URI.len())
+}
+
+/// Whether `uri` is relative to the playlist: not a URL with a scheme such
+/// as `https:` and not an absolute path.
+fn is_relative_uri(uri: &str) -> bool {
+    let scheme = uri.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    !scheme && !uri.starts_with(['/', '\\'])
+}
+
+/// The lines of `reader`, trimmed, without blank lines and the `#EXTM3U`
+/// header.
+fn playlist_lines<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<String>> {
+    reader
+        .lines()
+        .map(|line| line.map(|line| line.trim_start_matches('\u{feff}').trim().to_string()))
+        .filter(|line| {
+            line.as_ref()
+                .map_or(true, |line| !line.is_empty() && !is_tag(line, "#EXTM3U"))
+        })
+}
+
+/// An `#EXT-X-MEDIA` rendition of a master playlist, such as an audio
+/// track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    pub line: String,
+    pub attributes: Attributes,
+}
+
+/// A variant of a master playlist: an `#EXT-X-STREAM-INF` tag and the URI
+/// after it, or an `#EXT-X-I-FRAME-STREAM-INF` tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    /// The tag, other lines before the URI and the URI.
+    pub lines: Vec<String>,
+    pub attributes: Attributes,
+}
+
+impl Variant {
+    /// The value of `attribute`, such as `RESOLUTION`, or `fallback` if it
+    /// is missing or blank.
+    pub fn group<'a>(&'a self, attribute: &str, fallback: &'a str) -> &'a str {
+        self.attributes
+            .get(attribute)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .unwrap_or(fallback)
+    }
+}
+
+/// An HLS master playlist.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MasterPlaylist {
+    /// Tags that apply to the whole playlist, such as `#EXT-X-VERSION`, and
+    /// comments, in their original order.
+    pub tags: Vec<String>,
+    pub renditions: Vec<Rendition>,
+    pub variants: Vec<Variant>,
+}
+
+impl MasterPlaylist {
+    pub fn parse<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut playlist = MasterPlaylist::default();
+        // An #EXT-X-STREAM-INF waiting for its URI
+        let mut pending: Option<Variant> = None;
+        for line in playlist_lines(reader) {
+            let line = line?;
+            let name = tag_name(&line);
+            if let Some(list) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+                pending = Some(Variant {
+                    attributes: parse_attribute_list(list),
+                    lines: vec![line],
+                });
+            } else if let Some(list) = line.strip_prefix("#EXT-X-I-FRAME-STREAM-INF:") {
+                playlist.variants.push(Variant {
+                    attributes: parse_attribute_list(list),
+                    lines: vec![line],
+                });
+            } else if let Some(list) = line.strip_prefix("#EXT-X-MEDIA:") {
+                playlist.renditions.push(Rendition {
+                    attributes: parse_attribute_list(list),
+                    line,
+                });
+            } else if let Some(variant) = pending.as_mut() {
+                let uri = !line.starts_with('#');
+                variant.lines.push(line);
+                if uri {
+                    playlist.variants.extend(pending.take());
+                }
+            } else if name.starts_with('#') {
+                playlist.tags.push(line);
+            }
+        }
+        Ok(playlist)
+    }
+
+    /// The groups of variants by `attribute`, in order of first
+    /// appearance, with the number of variants in each. Variants without
+    /// the attribute are in the `fallback` group.
+    pub fn groups(&self, attribute: &str, fallback: &str) -> Vec<(String, usize)> {
+        let mut groups: Vec<(String, usize)> = Vec::new();
+        for variant in &self.variants {
+            let group = variant.group(attribute, fallback);
+            match groups.iter_mut().find(|(name, _)| name == group) {
+                Some((_, count)) => *count += 1,
+                None => groups.push((group.to_string(), 1)),
+            }
+        }
+        groups
+    }
+
+    /// A master playlist holding the variants for which `keep` returns
+    /// true and the renditions they refer to, with the same tags.
+    pub fn select(&self, mut keep: impl FnMut(&Variant) -> bool) -> MasterPlaylist {
+        let variants: Vec<Variant> = self
+            .variants
+            .iter()
+            .filter(|variant| keep(variant))
+            .cloned()
+            .collect();
+        let renditions = self
+            .renditions
+            .iter()
+            .filter(|rendition| {
+                let kind = rendition.attributes.get("TYPE").unwrap_or_default();
+                let group = rendition.attributes.get("GROUP-ID").unwrap_or_default();
+                RENDITION_GROUPS
+                    .iter()
+                    .filter(|(_, rendition_kind)| kind.eq_ignore_ascii_case(rendition_kind))
+                    .any(|(attribute, _)| {
+                        variants
+                            .iter()
+                            .any(|variant| variant.attributes.get(attribute) == Some(group))
+                    })
+            })
+            .cloned()
+            .collect();
+        MasterPlaylist {
+            tags: self.tags.clone(),
+            renditions,
+            variants,
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "#EXTM3U")?;
+        for line in &self.tags {
+            writeln!(writer, "{}", line)?;
+        }
+        for rendition in &self.renditions {
+            writeln!(writer, "{}", rendition.line)?;
+        }
+        for line in self.variants.iter().flat_map(|variant| &variant.lines) {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// A media segment: its `#EXTINF` line and other tags, and its URI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// The tags before the URI, such as `#EXTINF` and `#EXT-X-KEY`.
+    pub tags: Vec<String>,
+    pub uri: String,
+    /// Duration in seconds, from the `#EXTINF` line.
+    pub duration: f64,
+}
+
+impl Segment {
+    fn has_tag(&self, name: &str) -> bool {
+        self.tags.iter().any(|line| is_tag(line, name))
+    }
+
+    fn tags_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.tags.iter().filter(move |line| is_tag(line, name))
+    }
+}
+
+/// An HLS media playlist.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaPlaylist {
+    /// Tags that apply to the whole playlist, such as
+    /// `#EXT-X-TARGETDURATION`, in their original order. The sequence
+    /// numbers and `#EXT-X-ENDLIST` are kept apart.
+    pub tags: Vec<String>,
+    /// The sequence number of the first segment.
+    pub media_sequence: u64,
+    /// The discontinuity sequence number of the first segment.
+    pub discontinuity_sequence: u64,
+    pub segments: Vec<Segment>,
+    /// Whether no more segments will be added.
+    pub end_list: bool,
+}
+
+impl MediaPlaylist {
+    /// Parses a media playlist. Tags after the last segment, other than
+    /// `#EXT-X-ENDLIST`, are dropped.
+    pub fn parse<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut playlist = MediaPlaylist::default();
+        let mut tags = Vec::new();
+        for line in playlist_lines(reader) {
+            let line = line?;
+            let name = tag_name(&line);
+            if name == MEDIA_SEQUENCE {
+                playlist.media_sequence = parse_number(&line)?;
+            } else if name == DISCONTINUITY_SEQUENCE {
+                playlist.discontinuity_sequence = parse_number(&line)?;
+            } else if name == ENDLIST {
+                playlist.end_list = true;
+            } else if MEDIA_PLAYLIST_TAGS.contains(&name) {
+                playlist.tags.push(line);
+            } else if line.starts_with('#') {
+                tags.push(line);
+            } else {
+                let duration = tags
+                    .iter()
+                    .find(|line| is_tag(line, "#EXTINF"))
+                    .and_then(|line| parse_extinf(line).ok())
+                    .map_or(0.0, |extinf| extinf.duration);
+                playlist.segments.push(Segment {
+                    tags: std::mem::take(&mut tags),
+                    uri: line,
+                    duration,
+                });
+            }
+        }
+        Ok(playlist)
+    }
+
+    /// The total duration of the segments in seconds.
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+
+    /// Splits the playlist into consecutive chunks of at most `seconds`
+    /// each; a longer segment gets a chunk of its own. Every chunk is a
+    /// valid playlist: its sequence numbers count on from the chunks
+    /// before it, and the `#EXT-X-KEY` and `#EXT-X-MAP` tags in effect at
+    /// its first segment are repeated there.
+    pub fn chunks(&self, seconds: f64) -> Vec<MediaPlaylist> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut length = 0.0;
+        // The state at `start`: the keys and map in effect, and the
+        // discontinuities before it
+        let mut keys: Vec<&String> = Vec::new();
+        let mut map: Option<&String> = None;
+        let mut discontinuities = 0;
+        let (mut next_keys, mut next_map, mut next_discontinuities) = (Vec::new(), None, 0);
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > start && length + segment.duration > seconds + f64::EPSILON {
+                chunks.push(self.chunk(start..i, &keys, map, discontinuities));
+                start = i;
+                length = 0.0;
+                keys = next_keys.clone();
+                map = next_map;
+                discontinuities = next_discontinuities;
+            }
+            length += segment.duration;
+
+            if segment.has_tag(KEY) {
+                next_keys = segment.tags_named(KEY).collect();
+            }
+            next_map = segment.tags_named(MAP).last().or(next_map);
+            if segment.has_tag(DISCONTINUITY) {
+                next_discontinuities += 1;
+            }
+        }
+        if start < self.segments.len() {
+            chunks.push(self.chunk(start..self.segments.len(), &keys, map, discontinuities));
+        }
+        chunks
+    }
+
+    fn chunk(
+        &self,
+        range: std::ops::Range<usize>,
+        keys: &[&String],
+        map: Option<&String>,
+        discontinuities: u64,
+    ) -> MediaPlaylist {
+        let media_sequence = self.media_sequence + range.start as u64;
+        let mut segments = self.segments[range].to_vec();
+        let first = &mut segments[0];
+        if let Some(map) = map.filter(|_| !first.has_tag(MAP)) {
+            first.tags.insert(0, map.clone());
+        }
+        if !first.has_tag(KEY) {
+            first
+                .tags
+                .splice(0..0, keys.iter().map(|key| key.to_string()));
+        }
+        MediaPlaylist {
+            tags: self.tags.clone(),
+            media_sequence,
+            discontinuity_sequence: self.discontinuity_sequence + discontinuities,
+            segments,
+            end_list: self.end_list,
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "#EXTM3U")?;
+        for line in &self.tags {
+            writeln!(writer, "{}", line)?;
+        }
+        writeln!(writer, "{}:{}", MEDIA_SEQUENCE, self.media_sequence)?;
+        if self.discontinuity_sequence > 0 {
+            writeln!(
+                writer,
+                "{}:{}",
+                DISCONTINUITY_SEQUENCE, self.discontinuity_sequence
+            )?;
+        }
+        for segment in &self.segments {
+            for line in &segment.tags {
+                writeln!(writer, "{}", line)?;
+            }
+            writeln!(writer, "{}", segment.uri)?;
+        }
+        if self.end_list {
+            writeln!(writer, "{}", ENDLIST)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number after the colon of a tag such as `#EXT-X-MEDIA-SEQUENCE:10`.
+fn parse_number(line: &str) -> io::Result<u64> {
+    line.split_once(':')
+        .and_then(|(_, number)| number.trim().parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid number in {:?}", line),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = r#"#EXTM3U
+#EXT-X-VERSION:6
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",URI="audio/aac.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="ec3",NAME="English",URI="audio/ec3.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS="avc1.4d401e,mp4a.40.2",AUDIO="aac"
+360p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS="avc1.640028,ec-3",AUDIO="ec3"
+1080p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4000000,RESOLUTION=1920x1080,CODECS="avc1.640028,mp4a.40.2",AUDIO="aac"
+1080p-aac.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,RESOLUTION=640x360,URI="360p-iframes.m3u8"
+"#;
+
+    const MEDIA: &str = "#EXTM3U
+#EXT-X-VERSION:7
+#EXT-X-TARGETDURATION:6
+#EXT-X-MEDIA-SEQUENCE:100
+#EXT-X-PLAYLIST-TYPE:VOD
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXT-X-KEY:METHOD=AES-128,URI=\"key1\"
+#EXTINF:6.0,
+seg100.m4s
+#EXTINF:6.0,
+seg101.m4s
+#EXT-X-DISCONTINUITY
+#EXTINF:4.0,
+seg102.m4s
+#EXT-X-KEY:METHOD=AES-128,URI=\"key2\"
+#EXTINF:6.0,
+seg103.m4s
+#EXTINF:6.0,
+seg104.m4s
+#EXT-X-ENDLIST
+";
+
+    #[test]
+    fn test_rebase_uris() {
+        let playlist = "#EXTM3U\n\
+                        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",URI=\"audio/aac.m3u8\"\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"https://example.com/key\"\r\n\
+                        #EXT-X-CONTENT-STEERING:SERVER-URI=\"steering.json\"\n\
+                        #EXTINF:6.0,\n\
+                        seg100.m4s\n\
+                        /media/seg101.m4s\n\
+                        http://example.com/seg102.m4s\n";
+        assert_eq!(
+            rebase_uris(playlist, "../videos/"),
+            "#EXTM3U\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",URI=\"../videos/audio/aac.m3u8\"\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"https://example.com/key\"\r\n\
+             #EXT-X-CONTENT-STEERING:SERVER-URI=\"steering.json\"\n\
+             #EXTINF:6.0,\n\
+             ../videos/seg100.m4s\n\
+             /media/seg101.m4s\n\
+             http://example.com/seg102.m4s\n"
+        );
+        assert_eq!(rebase_uris(playlist, ""), playlist);
+    }
+
+    fn write_to_string(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut buffer = Vec::new();
+        write(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_detect_hls() {
+        let detect = |content: &str| detect_hls(content.as_bytes()).unwrap();
+        assert_eq!(detect(MASTER), Some(HlsKind::Master));
+        assert_eq!(detect(MEDIA), Some(HlsKind::Media));
+        assert_eq!(
+            detect("#EXTM3U\n#EXTINF:-1 group-title=\"News\",News\nhttp://a\n"),
+            None
+        );
+        assert_eq!(detect("music/song.mp3\n#EXT-X-ENDLIST\n"), None);
+        // #EXT-X-MEDIA is not #EXT-X-MEDIA-SEQUENCE
+        assert_eq!(
+            detect("#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:1\n"),
+            Some(HlsKind::Media)
+        );
+    }
+
+    #[test]
+    fn test_parse_attribute_list() {
+        let attributes = parse_attribute_list(
+            r#"BANDWIDTH=800000,CODECS="avc1.4d401e,mp4a.40.2", AUDIO="aac",RESOLUTION=640x360"#,
+        );
+        assert_eq!(attributes.get("bandwidth"), Some("800000"));
+        assert_eq!(attributes.get("CODECS"), Some("avc1.4d401e,mp4a.40.2"));
+        assert_eq!(attributes.get("AUDIO"), Some("aac"));
+        assert_eq!(attributes.get("RESOLUTION"), Some("640x360"));
+        assert_eq!(attributes.len(), 4);
+    }
+
+    #[test]
+    fn test_master_playlist_groups() {
+        let master = MasterPlaylist::parse(MASTER.as_bytes()).unwrap();
+        assert_eq!(master.tags, vec!["#EXT-X-VERSION:6"]);
+        assert_eq!(master.renditions.len(), 2);
+        assert_eq!(master.variants.len(), 4);
+        assert_eq!(
+            master.groups("resolution", "Unknown"),
+            vec![("640x360".to_string(), 2), ("1920x1080".to_string(), 2)]
+        );
+        assert_eq!(
+            master.groups("FRAME-RATE", "Unknown"),
+            vec![("Unknown".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn test_master_playlist_select() {
+        let master = MasterPlaylist::parse(MASTER.as_bytes()).unwrap();
+        let selected = master.select(|variant| variant.group("AUDIO", "none") == "aac");
+        assert_eq!(
+            write_to_string(|w| selected.write(w)),
+            "#EXTM3U\n\
+             #EXT-X-VERSION:6\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"audio/aac.m3u8\"\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\",AUDIO=\"aac\"\n\
+             360p.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=4000000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\",AUDIO=\"aac\"\n\
+             1080p-aac.m3u8\n"
+        );
+    }
+
+    #[test]
+    fn test_media_playlist_parse() {
+        let media = MediaPlaylist::parse(MEDIA.as_bytes()).unwrap();
+        assert_eq!(media.media_sequence, 100);
+        assert!(media.end_list);
+        assert_eq!(media.segments.len(), 5);
+        assert_eq!(media.duration(), 28.0);
+        assert_eq!(
+            media.tags,
+            vec![
+                "#EXT-X-VERSION:7",
+                "#EXT-X-TARGETDURATION:6",
+                "#EXT-X-PLAYLIST-TYPE:VOD"
+            ]
+        );
+        assert_eq!(media.segments[0].tags.len(), 3);
+
+        // Written back whole, it is the same playlist
+        let written = write_to_string(|w| media.write(w));
+        assert_eq!(MediaPlaylist::parse(written.as_bytes()).unwrap(), media);
+    }
+
+    #[test]
+    fn test_media_playlist_chunks() {
+        let media = MediaPlaylist::parse(MEDIA.as_bytes()).unwrap();
+        let chunks = media.chunks(12.0);
+        let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.segments.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(chunks[1].media_sequence, 102);
+        assert_eq!(chunks[2].media_sequence, 104);
+        assert_eq!(chunks[2].discontinuity_sequence, 1);
+        assert_eq!(
+            write_to_string(|w| chunks[2].write(w)),
+            "#EXTM3U\n\
+             #EXT-X-VERSION:7\n\
+             #EXT-X-TARGETDURATION:6\n\
+             #EXT-X-PLAYLIST-TYPE:VOD\n\
+             #EXT-X-MEDIA-SEQUENCE:104\n\
+             #EXT-X-DISCONTINUITY-SEQUENCE:1\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key2\"\n\
+             #EXT-X-MAP:URI=\"init.mp4\"\n\
+             #EXTINF:6.0,\n\
+             seg104.m4s\n\
+             #EXT-X-ENDLIST\n"
+        );
+
+        // A segment longer than the chunk duration gets a chunk of its own
+        assert_eq!(media.chunks(1.0).len(), 5);
+        assert_eq!(media.chunks(100.0).len(), 1);
+    }
+}
@@ -0,0 +1,515 @@
+//! Finding and reading input playlists.
+
+use crate::compress::{decompress, open_zip_member, zip_playlists, Compression};
+use crate::diagnostic::Diagnostic;
+use crate::encoding::{decode, InputEncoding};
+use crate::extinf::Attributes;
+use crate::group::{GroupSource, PlainGroup};
+use crate::parser::Entries;
+use crate::playlist::Entry;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+/// The input name that stands for standard input.
+pub const STDIN: &str = "-";
+
+/// Expands `patterns` into the files to read. A pattern containing `*`, `?`
+/// or `[` is a glob such as `providers/*.m3u`, whose matches are taken in
+/// sorted order; [`STDIN`] is kept as is; anything else is a path. A missing
+/// file or a glob that matches nothing is an error, as is reading the same
+/// file twice.
+pub fn expand_inputs(patterns: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    for pattern in patterns {
+        let text = pattern.to_string_lossy();
+        let matches = if text == STDIN {
+            vec![pattern.clone()]
+        } else if text.contains(['*', '?', '[']) && !pattern.exists() {
+            let paths =
+                glob::glob(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mut matches = paths
+                .map(|path| path.map_err(io::Error::from))
+                .collect::<io::Result<Vec<_>>>()?;
+            matches.retain(|path| path.is_file());
+            matches.sort();
+            if matches.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no input files match {:?}", text),
+                ));
+            }
+            matches
+        } else if pattern.is_file() {
+            vec![pattern.clone()]
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("input file does not exist: {:?}", pattern),
+            ));
+        };
+
+        for path in matches {
+            if inputs.contains(&path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("input file given more than once: {:?}", path),
+                ));
+            }
+            inputs.push(path);
+        }
+    }
+    Ok(inputs)
+}
+
+/// The name an input is known by when tagging channels with their source:
+/// its file name without extension, not counting a compression extension
+/// such as `.gz`, or `stdin`.
+pub fn source_name(path: &Path) -> String {
+    if path.as_os_str() == STDIN {
+        return "stdin".to_string();
+    }
+    let compressed = path.extension().is_some_and(|ext| {
+        ["gz", "xz", "zst", "zstd", "zip"]
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known))
+    });
+    let path = match path.file_stem() {
+        Some(stem) if compressed => Path::new(stem),
+        _ => path,
+    };
+    path.file_stem()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// One playlist to read: a file, standard input, or a playlist inside a zip
+/// archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input {
+    /// The file, or [`STDIN`].
+    pub path: PathBuf,
+    /// The playlist's name inside the zip archive at `path`.
+    pub member: Option<String>,
+}
+
+impl Input {
+    /// The name used when tagging channels with their source; see
+    /// [`source_name`].
+    pub fn name(&self) -> String {
+        match &self.member {
+            Some(member) => source_name(Path::new(member)),
+            None => source_name(&self.path),
+        }
+    }
+
+    fn is_stdin(&self) -> bool {
+        self.path.as_os_str() == STDIN
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_stdin() {
+            f.write_str("stdin")?;
+        } else {
+            write!(f, "{}", self.path.display())?;
+        }
+        match &self.member {
+            Some(member) => write!(f, "!{}", member),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The playlists of one run, which can be read any number of times.
+///
+/// Files are reopened for every read and decompressed on the fly when they
+/// start with gzip, xz or zstd data. Every `.m3u` and `.m3u8` member of a
+/// zip archive is an input of its own. Text is decoded into UTF-8 with
+/// [`decode`]. Standard input can only be read once, so it is copied into a
+/// temporary file when the inputs are opened, which is removed once the
+/// last clone of the inputs is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Inputs {
+    inputs: Vec<Input>,
+    stdin: Option<Arc<NamedTempFile>>,
+    encoding: InputEncoding,
+    lossy: bool,
+    lossless: bool,
+    group_source: GroupSource,
+    plain_group: PlainGroup,
+}
+
+impl Inputs {
+    /// Expands `patterns` with [`expand_inputs`], copying standard input
+    /// into a temporary file right away if it is among them.
+    pub fn open(patterns: &[PathBuf]) -> io::Result<Self> {
+        Self::open_with_stdin(patterns, io::stdin().lock())
+    }
+
+    /// Like [`Inputs::open`], reading `stdin` for [`STDIN`].
+    pub fn open_with_stdin<R: Read>(patterns: &[PathBuf], mut stdin: R) -> io::Result<Self> {
+        let paths = expand_inputs(patterns)?;
+        let mut inputs = Inputs::default();
+        for path in paths {
+            let input = Input { path, member: None };
+            if input.is_stdin() {
+                let mut spool = NamedTempFile::new()?;
+                io::copy(&mut stdin, &mut spool)?;
+                inputs.stdin = Some(Arc::new(spool));
+            }
+            let mut magic = Vec::with_capacity(8);
+            inputs.open_file(&input)?.take(8).read_to_end(&mut magic)?;
+            let members = match Compression::detect(&magic) {
+                Compression::Zip => Some(zip_playlists(inputs.open_file(&input)?)?),
+                _ => None,
+            };
+            let path = input.path;
+
+            match members {
+                None => inputs.inputs.push(Input { path, member: None }),
+                Some(members) if members.is_empty() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("no .m3u or .m3u8 playlists in zip archive {:?}", path),
+                    ))
+                }
+                Some(members) => inputs
+                    .inputs
+                    .extend(members.into_iter().map(|member| Input {
+                        path: path.clone(),
+                        member: Some(member),
+                    })),
+            }
+        }
+        Ok(inputs)
+    }
+
+    /// Reads every input as `encoding` instead of detecting it.
+    pub fn with_encoding(mut self, encoding: InputEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Replaces bytes that are invalid in the input encoding with U+FFFD
+    /// instead of failing.
+    pub fn with_lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Parses the inputs in lossless mode; see [`Entries::with_lossless`].
+    pub fn with_lossless(mut self, lossless: bool) -> Self {
+        self.lossless = lossless;
+        self
+    }
+
+    /// Reads the group of every entry from `source`; see
+    /// [`GroupSource::apply`].
+    pub fn with_group_source(mut self, source: GroupSource) -> Self {
+        self.group_source = source;
+        self
+    }
+
+    /// Puts plain entries into the group derived by `group`; see
+    /// [`PlainGroup::apply`].
+    pub fn with_plain_group(mut self, group: PlainGroup) -> Self {
+        self.plain_group = group;
+        self
+    }
+
+    /// Every input, in the order they are read.
+    pub fn as_slice(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Opens the file holding `input`: its path, or the copy of standard
+    /// input.
+    fn open_file(&self, input: &Input) -> io::Result<fs::File> {
+        match &self.stdin {
+            Some(spool) if input.is_stdin() => spool.reopen(),
+            _ => fs::File::open(&input.path),
+        }
+    }
+
+    /// Opens `input` for reading, decompressed and decoded into UTF-8. The
+    /// errors of opening and reading it name the input.
+    pub fn reader(&self, input: &Input) -> io::Result<Box<dyn BufRead + '_>> {
+        let open = || {
+            let file = self.open_file(input)?;
+            let reader: Box<dyn BufRead + '_> = match &input.member {
+                Some(member) => Box::new(BufReader::new(open_zip_member(file, member)?)),
+                None => Box::new(BufReader::new(file)),
+            };
+            decode(decompress(reader)?, self.encoding, self.lossy)
+        };
+        let reader = open().map_err(|e| input_error(input, e))?;
+        Ok(Box::new(NamedReader {
+            inner: reader,
+            input: input.to_string(),
+        }))
+    }
+
+    /// The `#EXTM3U` header attributes of all inputs, read from the lines
+    /// before their first channel. When inputs disagree on an attribute,
+    /// the first input wins.
+    pub fn header(&self) -> io::Result<Attributes> {
+        let mut header = Attributes::new();
+        for input in &self.inputs {
+            let mut entries = Entries::new(self.reader(input)?);
+            entries.next().transpose()?;
+            for (key, value) in entries.header().iter() {
+                if !header.contains_key(key) {
+                    header.insert(key, value);
+                }
+            }
+        }
+        Ok(header)
+    }
+
+    /// Streams the entries of every input, one after the other.
+    ///
+    /// With `tag`, every entry gets an attribute of that name holding its
+    /// input's [`Input::name`], written into its `#EXTINF` line as well.
+    /// Inputs are opened one at a time as they are reached, but every file
+    /// is checked up front, so a missing one fails before any entry is read.
+    pub fn entries(&self, tag: Option<&str>) -> io::Result<InputEntries<'_>> {
+        for input in self.inputs.iter().filter(|input| !input.is_stdin()) {
+            fs::metadata(&input.path).map_err(|e| input_error(input, e))?;
+        }
+        Ok(InputEntries {
+            inputs: self,
+            pending: self.inputs.iter(),
+            current: None,
+            tag: tag.map(str::to_string),
+            group_source: self.group_source,
+            plain_group: self.plain_group,
+            diagnostics: Vec::new(),
+        })
+    }
+}
+
+/// Prefixes the message of `error` with the input it happened in.
+fn input_error(input: &impl fmt::Display, error: io::Error) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", input, error))
+}
+
+/// A reader whose errors name the input being read.
+struct NamedReader<R> {
+    inner: R,
+    input: String,
+}
+
+impl<R: BufRead> Read for NamedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let input = &self.input;
+        self.inner.read(buf).map_err(|e| input_error(input, e))
+    }
+}
+
+impl<R: BufRead> BufRead for NamedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let input = &self.input;
+        self.inner.fill_buf().map_err(|e| input_error(input, e))
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+    }
+}
+
+type InputReader<'a> = Entries<Box<dyn BufRead + 'a>>;
+
+/// The entries of all [`Inputs`], returned by [`Inputs::entries`].
+pub struct InputEntries<'a> {
+    inputs: &'a Inputs,
+    // The inputs not opened yet
+    pending: std::slice::Iter<'a, Input>,
+    // The input being read, with its name for tagging
+    current: Option<(&'a Input, String, InputReader<'a>)>,
+    tag: Option<String>,
+    group_source: GroupSource,
+    plain_group: PlainGroup,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl InputEntries<'_> {
+    /// The problems found in the inputs read so far, each with the input
+    /// it was found in.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+impl Iterator for InputEntries<'_> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((input, source, entries)) = self.current.as_mut() {
+                match entries.next() {
+                    Some(Ok(mut entry)) => {
+                        self.group_source.apply(&mut entry);
+                        self.plain_group.apply(&mut entry);
+                        if let Some(tag) = &self.tag {
+                            entry.set_attribute(tag, source);
+                        }
+                        return Some(Ok(entry));
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => {
+                        let file = input.to_string();
+                        self.diagnostics.extend(
+                            entries
+                                .take_diagnostics()
+                                .into_iter()
+                                .map(|d| d.with_file(file.clone())),
+                        );
+                        self.current = None;
+                    }
+                }
+            }
+            let input = self.pending.next()?;
+            let entries = match self.inputs.reader(input) {
+                Ok(reader) => Entries::new(reader).with_lossless(self.inputs.lossless),
+                Err(e) => return Some(Err(e)),
+            };
+            self.current = Some((input, input.name(), entries));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_expand_inputs() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["b.m3u", "a.m3u", "notes.txt"] {
+            fs::write(temp_dir.path().join(name), "#EXTM3U\n").unwrap();
+        }
+        let a = temp_dir.path().join("a.m3u");
+        let b = temp_dir.path().join("b.m3u");
+
+        let inputs = expand_inputs(&[temp_dir.path().join("*.m3u")]).unwrap();
+        assert_eq!(inputs, vec![a.clone(), b.clone()]);
+
+        let inputs = expand_inputs(&[b.clone(), a.clone()]).unwrap();
+        assert_eq!(inputs, vec![b.clone(), a.clone()]);
+
+        assert!(expand_inputs(&[temp_dir.path().join("*.m3u8")]).is_err());
+        assert!(expand_inputs(&[temp_dir.path().join("missing.m3u")]).is_err());
+        assert!(expand_inputs(&[a, temp_dir.path().join("*.m3u")]).is_err());
+    }
+
+    #[test]
+    fn test_inputs_with_source_tag_and_stdin() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = temp_dir.path().join("provider-a.m3u");
+        fs::write(
+            &first,
+            "#EXTM3U\n#EXTINF:-1 group-title=\"News\",A News\nhttp://a/news\n",
+        )
+        .unwrap();
+        let stdin = "#EXTM3U\n#EXTINF:-1 group-title=\"News\",B News\nhttp://b/news\n";
+
+        let inputs =
+            Inputs::open_with_stdin(&[first, PathBuf::from(STDIN)], stdin.as_bytes()).unwrap();
+        // Standard input can be read again
+        for _ in 0..2 {
+            let entries = inputs
+                .entries(Some("source"))
+                .unwrap()
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].attributes.get("source"), Some("provider-a"));
+            assert_eq!(
+                entries[1].extinf_line,
+                r#"#EXTINF:-1 group-title="News" source="stdin",B News"#
+            );
+        }
+    }
+
+    #[test]
+    fn test_compressed_inputs() {
+        use std::io::Write;
+
+        let temp_dir = TempDir::new().unwrap();
+        let playlist = |title: &str| {
+            format!(
+                "#EXTM3U\n#EXTINF:-1 group-title=\"News\",{}\nhttp://example.com/{}\n",
+                title, title
+            )
+        };
+
+        let gzip_path = temp_dir.path().join("provider-a.m3u.gz");
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(playlist("a").as_bytes()).unwrap();
+        fs::write(&gzip_path, gzip.finish().unwrap()).unwrap();
+
+        let zip_path = temp_dir.path().join("archive.zip");
+        let mut archive = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, title) in [("b.m3u", "b"), ("notes.txt", "x"), ("c.M3U8", "c")] {
+            archive.start_file(name, options).unwrap();
+            archive.write_all(playlist(title).as_bytes()).unwrap();
+        }
+        archive.finish().unwrap();
+
+        let inputs = Inputs::open_with_stdin(&[gzip_path, zip_path], io::empty()).unwrap();
+        let names: Vec<String> = inputs.as_slice().iter().map(Input::name).collect();
+        assert_eq!(names, vec!["provider-a", "b", "c"]);
+        assert!(inputs.as_slice()[1]
+            .to_string()
+            .ends_with("archive.zip!b.m3u"));
+
+        let titles: Vec<String> = inputs
+            .entries(None)
+            .unwrap()
+            .map(|entry| entry.unwrap().title)
+            .collect();
+        assert_eq!(titles, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_inputs_decode_text() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("playlist.m3u");
+        fs::write(
+            &path,
+            b"\xef\xbb\xbf#EXTM3U\n#EXTINF:-1 group-title=\"Caf\xc3\xa9\",A\nhttp://a\n".as_slice(),
+        )
+        .unwrap();
+        let inputs = Inputs::open_with_stdin(std::slice::from_ref(&path), io::empty()).unwrap();
+        let entry = inputs.entries(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.group_name, "Café");
+
+        fs::write(&path, b"#EXTINF:-1 group-title=\"Caf\xe9\",A\nhttp://a\n").unwrap();
+        let entry = inputs.entries(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.group_name, "Café");
+
+        let inputs = inputs.with_encoding("utf-8".parse().unwrap());
+        let err = inputs.entries(None).unwrap().next().unwrap().unwrap_err();
+        assert!(err.to_string().starts_with(&path.display().to_string()));
+        assert!(err.to_string().ends_with("is not valid UTF-8 at byte 27"));
+        let entry = inputs.with_lossy(true).entries(None).unwrap().next();
+        assert_eq!(entry.unwrap().unwrap().group_name, "Caf\u{fffd}");
+    }
+}
@@ -0,0 +1,63 @@
+//! Parse, group and split M3U playlists.
+//!
+//! ```
+//! use m3u_splitter::{group_entries, parse_str, GroupBy, GroupKey};
+//!
+//! let playlist = parse_str(
+//!     "#EXTM3U\n\
+//!      #EXTINF:-1 group-title=\"News\",News 1\n\
+//!      http://example.com/news1\n",
+//! );
+//! let groups = group_entries(playlist, &GroupBy::default());
+//! assert_eq!(groups[&GroupKey::from("News")][0].title, "News 1");
+//! ```
+
+pub mod compress;
+pub mod dedupe;
+pub mod diagnostic;
+pub mod encoding;
+pub mod extinf;
+pub mod filename;
+pub mod filter;
+pub mod group;
+pub mod hls;
+pub mod input;
+pub mod parser;
+pub mod plan;
+pub mod playlist;
+pub mod rename;
+pub mod split;
+pub mod template;
+pub mod writer;
+
+pub use compress::{decompress, CompressedWriter, Compression, OutputCompression};
+pub use dedupe::{DedupeKey, Deduplicator, Duplicate, Duplicates, EntrySummary, KeepPolicy};
+pub use diagnostic::{Diagnostic, Severity};
+pub use encoding::{decode, detect_encoding, InputEncoding};
+pub use extinf::{
+    format_header, parse_extinf, parse_header, remove_extinf_attribute, set_extinf_attribute,
+    Attributes, Extinf, ExtinfError, HeaderError,
+};
+pub use filename::{sanitize_filename, FilenameStrategy};
+pub use filter::{Condition, ConditionError, EntryFilter, GroupFilter, Pattern, PatternError};
+pub use group::{
+    count_groups, group_entries, GroupBy, GroupKey, GroupOutput, GroupSort, GroupSource,
+    GroupStats, Groups, PlainGroup,
+};
+pub use hls::{
+    detect_hls, parse_attribute_list, rebase_uris, HlsKind, MasterPlaylist, MediaPlaylist,
+    Rendition, Segment, Variant,
+};
+pub use input::{expand_inputs, source_name, Input, InputEntries, Inputs, STDIN};
+pub use parser::{
+    entries, entries_from_file, parse_file, parse_lossless, parse_reader, parse_str, Entries,
+};
+pub use plan::{candidate_paths, Collision, CollisionError, CollisionPolicy, OutputPlan};
+pub use playlist::{Entry, Playlist, DEFAULT_GROUP, EXTGRP};
+pub use rename::{GroupRenames, RenameError, RenameRule};
+pub use split::{split_reader, Splitter, WrittenGroup};
+pub use template::{NameContext, NameTemplate, TemplateError};
+pub use writer::{
+    group_dir_path, group_file_path, write_entry, write_group_file, write_header,
+    write_header_with_attributes, write_lossless, write_playlist,
+};
@@ -1,152 +1,501 @@
 use clap::Parser;
+use m3u_splitter::{
+    candidate_paths, count_groups, detect_hls, format_header, rebase_uris, write_entry,
+    write_header_with_attributes, CollisionPolicy, Condition, DedupeKey, Deduplicator, Diagnostic,
+    Duplicates, Entry, EntryFilter, FilenameStrategy, GroupBy, GroupFilter, GroupKey, GroupOutput,
+    GroupRenames, GroupSort, GroupSource, HlsKind, Input, InputEncoding, Inputs, KeepPolicy,
+    MasterPlaylist, MediaPlaylist, NameTemplate, OutputCompression, OutputPlan, Pattern,
+    PlainGroup, Severity, Splitter, DEFAULT_GROUP, STDIN,
+};
 use std::collections::HashMap;
 use std::fs;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "m3u-splitter")]
 #[command(about = "Splits M3U playlist files by group-name")]
 struct Args {
-    /// Input M3U file path
-    #[arg(short, long)]
-    input: PathBuf,
+    /// Input M3U file paths or glob patterns such as "providers/*.m3u", or
+    /// "-" for stdin; all inputs are split together
+    #[arg(short, long, required = true, num_args = 1..)]
+    input: Vec<PathBuf>,
 
     /// Output directory for split M3U files
-    #[arg(short, long)]
-    output: PathBuf,
+    #[arg(short, long, required_unless_present = "extract")]
+    output: Option<PathBuf>,
+
+    /// Write the channels of groups matching GROUP (same syntax as
+    /// --include-group) to stdout as one playlist instead of splitting into
+    /// files. Can be repeated
+    #[arg(
+        long,
+        visible_alias = "group",
+        value_name = "GROUP",
+        conflicts_with_all = ["output", "dry_run"]
+    )]
+    extract: Vec<String>,
+
+    /// Add an attribute (default: "source") holding each channel's input
+    /// file name, e.g. to split by source with --by source
+    #[arg(long, value_name = "ATTR", num_args = 0..=1, default_missing_value = "source")]
+    tag_source: Option<String>,
+
+    /// Text encoding of the inputs: auto (detect from a byte order mark or
+    /// the content) or a label such as utf-8, windows-1252 or iso-8859-2.
+    /// Output is always UTF-8
+    #[arg(long, value_name = "ENCODING", default_value_t = InputEncoding::Auto)]
+    encoding: InputEncoding,
+
+    /// Replace bytes that are invalid in the input encoding with U+FFFD
+    /// instead of failing
+    #[arg(long)]
+    lossy: bool,
+
+    /// Keep comments, blank lines and unknown directives such as #PLAYLIST:
+    /// before each channel, and write every channel exactly as it was read
+    #[arg(long)]
+    lossless: bool,
+
+    /// Fail if the inputs have any problem, such as an #EXTINF without URL
+    /// or a line that belongs to no channel, before writing anything
+    #[arg(long)]
+    strict: bool,
 
     /// Dry run: only show statistics without writing files
     #[arg(long)]
     dry_run: bool,
-}
 
-#[derive(Debug)]
-struct Channel {
-    extinf_line: String,
-    url: String,
-    group_name: String,
-}
+    /// EXTINF attribute to split by (e.g. tvg-country, tvg-language); a
+    /// comma-separated list writes nested directories, outermost first
+    #[arg(
+        long,
+        value_name = "ATTR",
+        value_delimiter = ',',
+        default_value = "group-title"
+    )]
+    by: Vec<String>,
+
+    /// Group for channels where the split attribute is missing or empty
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_GROUP)]
+    fallback: String,
+
+    /// Split multi-valued attributes on SEP and put the channel into every
+    /// group it lists (default SEP: ";")
+    #[arg(long, value_name = "SEP", num_args = 0..=1, default_missing_value = ";")]
+    split_values: Option<String>,
+
+    /// With nested --by, also write an aggregate playlist for every
+    /// directory level (e.g. UK.m3u next to UK/)
+    #[arg(long)]
+    aggregate: bool,
+
+    /// Output file name template, e.g. "{index:03}-{group}-{count}.m3u8".
+    /// Placeholders: {group}, {count}, {index}, {stem} and the --by attributes
+    #[arg(long, value_name = "TEMPLATE")]
+    name_template: Option<String>,
+
+    /// How group names become file names: ascii (drop non-ASCII), unicode
+    /// (keep letters, strip only characters the OS rejects), transliterate
+    /// (Café -> Cafe) or slug (kids-family)
+    #[arg(long, value_name = "STRATEGY", default_value_t = FilenameStrategy::Ascii)]
+    filenames: FilenameStrategy,
+
+    /// What to do when groups map to the same file name: suffix (append
+    /// -2, -3, ...), merge (write them into one file) or error
+    #[arg(long, value_name = "POLICY", default_value_t = CollisionPolicy::Suffix)]
+    on_collision: CollisionPolicy,
+
+    /// Set an attribute of the #EXTM3U header of every output file, e.g.
+    /// url-tvg=http://example.com/epg.xml. Can be repeated
+    #[arg(long = "header", value_name = "KEY=VALUE", value_parser = parse_header_attribute)]
+    header: Vec<(String, String)>,
+
+    /// Leave out the #EXTM3U header attributes copied from the inputs that
+    /// match PATTERN, such as "x-*"; "*" leaves out all. Can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    strip_header: Vec<String>,
+
+    /// Compress the output files: none or gz (adds .gz to every file name)
+    #[arg(long, value_name = "FORMAT", default_value_t = OutputCompression::None, conflicts_with = "extract")]
+    compress: OutputCompression,
+
+    /// Where a channel's group is read from when it has both a group-title
+    /// attribute and an #EXTGRP line: group-title or extgrp. The other one
+    /// is used when the preferred one is missing
+    #[arg(long, value_name = "SOURCE", default_value_t = GroupSource::GroupTitle)]
+    group_source: GroupSource,
+
+    /// How groups are written: as-is, group-title (attribute only), extgrp
+    /// (#EXTGRP line only) or both
+    #[arg(long, value_name = "FORM", default_value_t = GroupOutput::AsIs)]
+    group_output: GroupOutput,
+
+    /// Group of plain entries, i.e. lines holding just a path or URL:
+    /// fallback (see --fallback), or their URL host, parent directory or
+    /// file extension
+    #[arg(long, value_name = "KEY", default_value_t = PlainGroup::Fallback)]
+    plain_group: PlainGroup,
+
+    /// Attribute of #EXT-X-STREAM-INF to split an HLS master playlist by,
+    /// such as RESOLUTION (default), CODECS, AUDIO or BANDWIDTH
+    #[arg(long, value_name = "ATTR")]
+    variant_by: Option<String>,
+
+    /// Cut an HLS media playlist into chunks of at most SECONDS each instead
+    /// of rejecting it
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    hls_chunk: Option<f64>,
+
+    /// Order of groups in the statistics and output files: first-seen
+    /// (order of first appearance), name or count
+    #[arg(long, value_name = "ORDER", default_value_t = GroupSort::FirstSeen)]
+    sort: GroupSort,
+
+    /// Reverse the --sort order
+    #[arg(long)]
+    desc: bool,
 
-fn parse_group_name(extinf_line: &str) -> Option<String> {
-    // Look for group-title="..." or group-title='...'
-    // Try double quotes first
-    if let Some(start) = extinf_line.find("group-title=\"") {
-        let start = start + "group-title=\"".len();
-        if let Some(end) = extinf_line[start..].find('"') {
-            return Some(extinf_line[start..start + end].to_string());
-        }
-    }
+    /// Only write groups matching PATTERN: an exact name, a glob such as
+    /// "UK*" or a regex prefixed with "re:". Can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    include_group: Vec<String>,
 
-    // Try single quotes
-    if let Some(start) = extinf_line.find("group-title='") {
-        let start = start + "group-title='".len();
-        if let Some(end) = extinf_line[start..].find('\'') {
-            return Some(extinf_line[start..start + end].to_string());
-        }
-    }
+    /// Leave out groups matching PATTERN, with the same syntax as
+    /// --include-group. Can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    exclude_group: Vec<String>,
 
-    None
+    /// Match --include-group and --exclude-group case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// Only keep channels meeting CONDITION: FIELD~REGEX, FIELD!~REGEX,
+    /// FIELD=VALUE, FIELD!=VALUE, FIELD or !FIELD, where FIELD is title, url
+    /// or an EXTINF attribute. Can be repeated; all must hold
+    #[arg(long = "where", value_name = "CONDITION")]
+    conditions: Vec<Condition>,
+
+    /// Rename groups before splitting using a TOML or CSV mapping file of
+    /// exact, glob or "re:" regex rules
+    #[arg(long, value_name = "FILE")]
+    rename: Option<PathBuf>,
+
+    /// With --rename, also write the new group-title into each renamed
+    /// channel's EXTINF line
+    #[arg(long, requires = "rename")]
+    rewrite_group_title: bool,
+
+    /// Remove duplicate channels, comparing by url, normalized-url (without
+    /// query string), tvg-id or title (normalized)
+    #[arg(long, value_name = "KEY")]
+    dedupe: Option<DedupeKey>,
+
+    /// Which duplicate --dedupe keeps: first, last or best (most
+    /// attributes)
+    #[arg(long, value_name = "POLICY", default_value_t = KeepPolicy::First)]
+    keep: KeepPolicy,
 }
 
-fn parse_m3u_file(input_path: &Path) -> io::Result<Vec<Channel>> {
-    let file = fs::File::open(input_path)?;
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+fn main() {
+    // Errors are returned rather than exiting on the spot, so that open
+    // files are finished and temporary files removed first
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-    let mut channels = Vec::new();
-    let mut i = 0;
+/// An error in the command line or the inputs, such as an invalid pattern.
+fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
 
-    while i < lines.len() {
-        let line = lines[i].trim();
+fn run(args: Args) -> io::Result<()> {
+    // Validate input files exist
+    let inputs = Inputs::open(&args.input)?
+        .with_encoding(args.encoding)
+        .with_lossy(args.lossy)
+        .with_lossless(args.lossless)
+        .with_group_source(args.group_source)
+        .with_plain_group(args.plain_group);
+    let tag = args.tag_source.as_deref();
+
+    let name_template = match &args.name_template {
+        Some(template) => Some(
+            NameTemplate::parse(template, &args.by)
+                .map_err(invalid)?
+                .with_strategy(args.filenames),
+        ),
+        None => None,
+    };
+
+    let parse_pattern = |pattern: &str| Pattern::new(pattern, args.ignore_case).map_err(invalid);
+    let mut filter = GroupFilter::new();
+    for pattern in &args.include_group {
+        filter = filter.include(parse_pattern(pattern)?);
+    }
+    for pattern in &args.exclude_group {
+        filter = filter.exclude(parse_pattern(pattern)?);
+    }
+    let mut extract = GroupFilter::new();
+    for pattern in &args.extract {
+        extract = extract.include(parse_pattern(pattern)?);
+    }
 
-        if line.starts_with("#EXTINF:") {
-            if i + 1 < lines.len() {
-                let extinf_line = line.to_string();
-                let url = lines[i + 1].trim().to_string();
+    // HLS playlists list stream variants or media segments rather than
+    // channels, and are split on their own terms
+    for input in inputs.as_slice() {
+        if let Some(kind) = detect_hls(inputs.reader(input)?)? {
+            return split_hls(&args, &inputs, input, kind, &filter);
+        }
+    }
+    if args.hls_chunk.is_some() {
+        return Err(invalid("--hls-chunk only applies to HLS media playlists"));
+    }
+    if args.variant_by.is_some() {
+        return Err(invalid("--variant-by only applies to HLS master playlists"));
+    }
 
-                let group_name =
-                    parse_group_name(&extinf_line).unwrap_or_else(|| "Unknown".to_string());
+    // The inputs' header attributes, such as the EPG link, are copied into
+    // every output file
+    let mut header = inputs.header()?;
+    let strip = args
+        .strip_header
+        .iter()
+        .map(|pattern| Pattern::new(pattern, true).map_err(invalid))
+        .collect::<io::Result<Vec<_>>>()?;
+    header.retain(|key, _| !strip.iter().any(|pattern| pattern.is_match(key)));
+    for (key, value) in &args.header {
+        header.insert(key, value);
+    }
 
-                channels.push(Channel {
-                    extinf_line,
-                    url,
-                    group_name,
-                });
+    let renames = match &args.rename {
+        Some(path) => GroupRenames::from_file(path)
+            .map_err(invalid)?
+            .with_rewrite_extinf(args.rewrite_group_title),
+        None => GroupRenames::new(),
+    };
+
+    let entry_filter = args
+        .conditions
+        .iter()
+        .cloned()
+        .fold(EntryFilter::new(), EntryFilter::with_condition);
+
+    let mut group_by = GroupBy::new(&args.by[0], &args.fallback);
+    for attribute in &args.by[1..] {
+        group_by = group_by.then_by(attribute);
+    }
+    if let Some(delimiter) = &args.split_values {
+        group_by = group_by.with_delimiter(delimiter);
+    }
+    // Deciding which duplicate to keep takes the whole input, so dedupe
+    // needs a pass of its own before the channels are counted. Channels
+    // left out by the group filter are skipped, so that a copy in an
+    // excluded group never wins over one that is written.
+    let duplicates = match args.dedupe {
+        Some(key) => {
+            let mut deduplicator = Deduplicator::new(key, args.keep);
+            for entry in inputs.entries(tag)? {
+                let mut entry = entry?;
+                renames.apply(&mut entry);
+                if !entry_filter.matches(&entry) {
+                    continue;
+                }
+                if group_by.keys(&entry).iter().any(|key| filter.matches(key)) {
+                    deduplicator.record(&entry);
+                } else {
+                    deduplicator.skip();
+                }
+            }
+            Some(deduplicator.finish())
+        }
+        None => None,
+    };
+    let is_duplicate = |index: usize| duplicates.as_ref().is_some_and(|d| d.is_removed(index));
+    let mut pipeline = Pipeline {
+        renames: &renames,
+        entry_filter: &entry_filter,
+        duplicates: duplicates.as_ref(),
+        group_by: &group_by,
+        filter: &filter,
+        group_output: args.group_output,
+        index: 0,
+    };
+
+    // Extract mode: a single pass straight to stdout, with messages on
+    // stderr so that the output stays a valid playlist
+    if !args.extract.is_empty() {
+        // Output starts right away, so check the inputs first
+        if args.strict {
+            let mut entries = inputs.entries(None)?;
+            for entry in entries.by_ref() {
+                entry?;
+            }
+            report_diagnostics(entries.diagnostics(), true)?;
+        }
 
-                i += 2;
-            } else {
-                // EXTINF line without URL, skip it
-                i += 1;
+        let mut writer = BufWriter::new(io::stdout().lock());
+        write_header_with_attributes(&mut writer, &header)?;
+        let mut extracted = 0;
+        let mut entries = inputs.entries(tag)?;
+        for entry in entries.by_ref() {
+            let mut entry = entry?;
+            if pipeline
+                .keys(&mut entry)
+                .iter()
+                .any(|key| extract.matches(key))
+            {
+                write_entry(&mut writer, &entry)?;
+                extracted += 1;
             }
-        } else {
-            i += 1;
         }
+        writer.flush()?;
+        eprintln!("Extracted {} channels", extracted);
+        report_diagnostics(entries.diagnostics(), args.strict)?;
+        return Ok(());
     }
 
-    Ok(channels)
-}
-
-fn sanitize_filename(group_name: &str) -> String {
-    // Remove non-ASCII characters and keep only safe filesystem characters
-    group_name
-        .chars()
-        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_' || *c == ' ')
-        .collect::<String>()
-        .trim()
-        .replace(' ', "_")
-}
-
-fn write_group_file(output_dir: &Path, group_name: &str, channels: &[Channel]) -> io::Result<()> {
-    let sanitized_name = sanitize_filename(group_name);
-    let filename = format!("{}.m3u", sanitized_name);
-    let filepath = output_dir.join(&filename);
-
-    let mut file = fs::File::create(&filepath)?;
-
-    // Write M3U header
-    writeln!(file, "#EXTM3U")?;
+    // First pass: count channels per group without keeping them in memory
+    if let [input] = inputs.as_slice() {
+        println!("Parsing M3U file: {:?}", input.to_string());
+    } else {
+        println!("Parsing {} M3U files:", inputs.len());
+        for input in inputs.as_slice() {
+            println!("  {:?}", input.to_string());
+        }
+    }
+    if !header.is_empty() {
+        println!("Header: {}", format_header(&header));
+    }
 
-    // Write each channel
-    for channel in channels {
-        writeln!(file, "{}", channel.extinf_line)?;
-        writeln!(file, "{}", channel.url)?;
+    let mut renamed = 0;
+    let mut dropped = 0;
+    let mut entries = inputs.entries(tag)?;
+    let kept = entries
+        .by_ref()
+        .map(|entry| {
+            entry.map(|mut entry| {
+                if renames.apply(&mut entry) {
+                    renamed += 1;
+                }
+                entry
+            })
+        })
+        .filter(|entry| match entry {
+            Ok(entry) if !entry_filter.matches(entry) => {
+                dropped += 1;
+                false
+            }
+            _ => true,
+        })
+        .enumerate()
+        .filter(|(index, _)| !is_duplicate(*index))
+        .map(|(_, entry)| entry);
+    let mut stats = count_groups(kept, &group_by)?;
+    report_diagnostics(entries.diagnostics(), args.strict)?;
+    stats.sort(args.sort, args.desc);
+    let filtered_out = stats.retain(|key| filter.matches(key));
+
+    if stats.is_empty() {
+        if dropped > 0 {
+            eprintln!("Warning: All {} channels were dropped by --where", dropped);
+        } else {
+            eprintln!("Warning: No channels found in the M3U file");
+        }
+        return Ok(());
     }
 
-    Ok(())
-}
+    // With several inputs, {stem} is the name of the first one
+    let stem = inputs.as_slice()[0].name();
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
+    // Decide every output path before writing anything
+    let candidates = candidate_paths(
+        &stats,
+        name_template.as_ref(),
+        args.filenames,
+        &stem,
+        args.aggregate,
+    );
+    let plan = OutputPlan::resolve(candidates, args.on_collision).map_err(invalid)?;
 
-    // Validate input file exists
-    if !args.input.exists() {
-        eprintln!("Error: Input file does not exist: {:?}", args.input);
-        std::process::exit(1);
+    // Display statistics
+    if filtered_out.is_empty() {
+        println!("\nFound {} groups:", stats.order.len());
+    } else {
+        println!(
+            "\nFound {} groups ({} filtered out):",
+            stats.order.len() + filtered_out.len(),
+            filtered_out.len()
+        );
     }
-
-    // Parse M3U file
-    println!("Parsing M3U file: {:?}", args.input);
-    let channels = parse_m3u_file(&args.input)?;
-
-    if channels.is_empty() {
-        eprintln!("Warning: No channels found in the M3U file");
-        return Ok(());
+    for group_name in &stats.order {
+        let count = stats.counts[group_name];
+        match plan.path(group_name).filter(|_| name_template.is_some()) {
+            Some(path) => println!(
+                "  {}: {} channels -> {}",
+                group_name,
+                count,
+                args.compress.file_path(path).display()
+            ),
+            None => println!("  {}: {} channels", group_name, count),
+        }
     }
-
-    // Group channels by group-name
-    let mut groups: HashMap<String, Vec<Channel>> = HashMap::new();
-    for channel in channels {
-        groups
-            .entry(channel.group_name.clone())
-            .or_default()
-            .push(channel);
+    if let Some(path) = &args.rename {
+        println!(
+            "\nRenamed the group of {} channels using {:?}",
+            renamed, path
+        );
+    }
+    if dropped > 0 {
+        println!("\nDropped {} channels by --where", dropped);
+    }
+    if let (Some(duplicates), Some(key)) = (&duplicates, args.dedupe) {
+        println!(
+            "\nRemoved {} duplicate channels (by {}, keeping {}):",
+            duplicates.removed_count(),
+            key,
+            args.keep
+        );
+        for duplicate in duplicates.iter() {
+            println!(
+                "  {}: kept \"{}\" ({})",
+                duplicate.value, duplicate.kept.title, duplicate.kept.group
+            );
+            for removed in &duplicate.removed {
+                println!(
+                    "    removed \"{}\" ({}) {}",
+                    removed.title, removed.group, removed.url
+                );
+            }
+        }
+    }
+    if !filtered_out.is_empty() {
+        println!("\nFiltered out {} groups:", filtered_out.len());
+        for (group_name, count) in &filtered_out {
+            println!("  {}: {} channels", group_name, count);
+        }
+    }
+    if group_by.delimiter().is_some() {
+        println!(
+            "\n{} of {} channels are in more than one group",
+            stats.multi_group_entries, stats.entries
+        );
     }
 
-    // Display statistics
-    println!("\nFound {} groups:", groups.len());
-    for (group_name, channels) in &groups {
-        println!("  {}: {} channels", group_name, channels.len());
+    if !plan.collisions().is_empty() {
+        println!(
+            "\nResolved {} file name collision(s) ({}):",
+            plan.collisions().len(),
+            args.on_collision
+        );
+        for collision in plan.collisions() {
+            println!("  {}:", collision.path.display());
+            for (key, path) in collision.keys.iter().zip(&collision.resolved) {
+                println!("    {} -> {}", key, path.display());
+            }
+        }
     }
 
     if args.dry_run {
@@ -154,17 +503,57 @@ fn main() -> io::Result<()> {
         return Ok(());
     }
 
-    // Create output directory if it doesn't exist
-    fs::create_dir_all(&args.output)?;
+    let output_dir = args
+        .output
+        .as_ref()
+        .expect("--output is required without --extract");
 
-    // Write output files
-    println!("\nWriting output files to: {:?}", args.output);
-    for (group_name, channels) in groups {
-        write_group_file(&args.output, &group_name, &channels)?;
+    // Create output directory if it doesn't exist
+    fs::create_dir_all(output_dir)?;
+
+    // Second pass: stream each channel into its group's file
+    println!("\nWriting output files to: {:?}", output_dir);
+    // Files are listed in --sort order, aggregates before their first group
+    let mut file_order: HashMap<PathBuf, usize> = HashMap::new();
+    for key in &stats.order {
+        let ancestors = key.ancestors().filter(|_| args.aggregate);
+        for key in ancestors.chain(std::iter::once(key.clone())) {
+            if let Some(path) = plan.path(&key) {
+                let path = args.compress.file_path(&output_dir.join(path));
+                let next = file_order.len();
+                file_order.entry(path).or_insert(next);
+            }
+        }
+    }
+    let mut splitter = Splitter::new(output_dir)
+        .with_strategy(args.filenames)
+        .with_aggregates(args.aggregate)
+        .with_compression(args.compress)
+        .with_header(header)
+        .with_paths(plan.into_paths());
+    for entry in inputs.entries(tag)? {
+        let mut entry = entry?;
+        let keys = pipeline.keys(&mut entry);
+        if !keys.is_empty() {
+            splitter.write_all(&keys, &entry)?;
+        }
+    }
+    let mut written = splitter.finish()?;
+    written.sort_by_key(|group| file_order.get(&group.path).copied());
+    for group in written {
+        let path = group.path.strip_prefix(output_dir).unwrap_or(&group.path);
+        let note = if group.aggregate {
+            ", aggregate".to_string()
+        } else if group.keys.len() > 1 {
+            format!(", merged from {} groups", group.keys.len())
+        } else {
+            String::new()
+        };
         println!(
-            "  Created: {}.m3u ({} channels)",
-            sanitize_filename(&group_name),
-            channels.len()
+            "  Created: {} ({} channels{})",
+            path.display(),
+            group.count,
+            note
         );
     }
 
@@ -172,201 +561,301 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::TempDir;
-
-    #[test]
-    fn test_parse_group_name_double_quotes() {
-        let line = r#"#EXTINF:-1 group-title="Sports" tvg-id="channel1",Channel Name"#;
-        assert_eq!(parse_group_name(line), Some("Sports".to_string()));
+/// Splits an HLS playlist: a master playlist into one playlist per group
+/// of variants by --variant-by, a media playlist into chunks of
+/// --hls-chunk seconds.
+fn split_hls(
+    args: &Args,
+    inputs: &Inputs,
+    input: &Input,
+    kind: HlsKind,
+    filter: &GroupFilter,
+) -> io::Result<()> {
+    if inputs.len() > 1 {
+        return Err(invalid(format!(
+            "{} is an HLS {} playlist, which has to be split on its own",
+            input, kind
+        )));
     }
-
-    #[test]
-    fn test_parse_group_name_single_quotes() {
-        let line = r#"#EXTINF:-1 group-title='News' tvg-id="channel2",Another Channel"#;
-        assert_eq!(parse_group_name(line), Some("News".to_string()));
+    if !args.extract.is_empty() {
+        return Err(invalid("--extract does not apply to HLS playlists"));
     }
-
-    #[test]
-    fn test_parse_group_name_with_spaces() {
-        let line = r#"#EXTINF:-1 group-title="Kids & Family" tvg-id="channel3",Kids Channel"#;
-        assert_eq!(parse_group_name(line), Some("Kids & Family".to_string()));
+    // Options about channels and their #EXTM3U header have nothing to act
+    // on, and would otherwise be ignored
+    let master = kind == HlsKind::Master;
+    let channel_options = [
+        (!args.conditions.is_empty(), "--where"),
+        (args.dedupe.is_some(), "--dedupe"),
+        (args.keep != KeepPolicy::First, "--keep"),
+        (args.rename.is_some(), "--rename"),
+        (args.tag_source.is_some(), "--tag-source"),
+        (args.by != ["group-title"], "--by"),
+        (args.split_values.is_some(), "--split-values"),
+        (args.aggregate, "--aggregate"),
+        (args.name_template.is_some(), "--name-template"),
+        (args.sort != GroupSort::FirstSeen, "--sort"),
+        (args.desc, "--desc"),
+        (!args.header.is_empty(), "--header"),
+        (!args.strip_header.is_empty(), "--strip-header"),
+        (args.strict, "--strict"),
+        (args.lossless, "--lossless"),
+        (
+            args.group_source != GroupSource::GroupTitle,
+            "--group-source",
+        ),
+        (args.group_output != GroupOutput::AsIs, "--group-output"),
+        (args.plain_group != PlainGroup::Fallback, "--plain-group"),
+        // Each kind of HLS playlist has its own options
+        (master && args.hls_chunk.is_some(), "--hls-chunk"),
+        (!master && args.variant_by.is_some(), "--variant-by"),
+        (!master && !args.include_group.is_empty(), "--include-group"),
+        (!master && !args.exclude_group.is_empty(), "--exclude-group"),
+    ];
+    if let Some((_, option)) = channel_options.iter().find(|(given, _)| *given) {
+        return Err(invalid(format!(
+            "{} does not apply to HLS {} playlists",
+            option, kind
+        )));
     }
 
-    #[test]
-    fn test_parse_group_name_missing() {
-        let line = r#"#EXTINF:-1 tvg-id="channel4",Channel Without Group"#;
-        assert_eq!(parse_group_name(line), None);
-    }
+    // HLS playlists are small, so every output file is rendered up front:
+    // its path, a note for the summary and its contents
+    let mut files: Vec<(PathBuf, String, Vec<u8>)> = Vec::new();
+    match kind {
+        HlsKind::Master => {
+            let master = MasterPlaylist::parse(inputs.reader(input)?)?;
+            println!("Parsing HLS master playlist: {:?}", input.to_string());
+
+            let variant_by = args.variant_by.as_deref().unwrap_or(DEFAULT_VARIANT_BY);
+            let mut groups = master.groups(variant_by, &args.fallback);
+            let total = groups.len();
+            groups.retain(|(group, _)| filter.matches(&GroupKey::from(group.as_str())));
+            let candidates = groups.iter().map(|(group, _)| {
+                let path = format!("{}.m3u8", args.filenames.file_stem(group));
+                (GroupKey::from(group.as_str()), PathBuf::from(path))
+            });
+            let plan = OutputPlan::resolve(candidates, args.on_collision).map_err(invalid)?;
+
+            if groups.len() == total {
+                println!("\nFound {} variant groups by {}:", total, variant_by);
+            } else {
+                println!(
+                    "\nFound {} variant groups by {} ({} filtered out):",
+                    total,
+                    variant_by,
+                    total - groups.len()
+                );
+            }
+            for (group, count) in &groups {
+                println!("  {}: {} variants", group, count);
+            }
 
-    #[test]
-    fn test_parse_group_name_empty() {
-        let line = r#"#EXTINF:-1 group-title="" tvg-id="channel5",Empty Group"#;
-        assert_eq!(parse_group_name(line), Some("".to_string()));
+            for path in plan.files() {
+                // Several groups share a file under --on-collision merge
+                let playlist = master.select(|variant| {
+                    let group = GroupKey::from(variant.group(variant_by, &args.fallback));
+                    plan.path(&group) == Some(path.as_path())
+                });
+                let mut contents = Vec::new();
+                playlist.write(&mut contents)?;
+                let note = format!("{} variants", playlist.variants.len());
+                files.push((path.clone(), note, contents));
+            }
+        }
+        HlsKind::Media => {
+            let media = MediaPlaylist::parse(inputs.reader(input)?)?;
+            let Some(seconds) = args.hls_chunk else {
+                return Err(invalid(format!(
+                    "{} is an HLS media playlist of {} segments, not a list of channels. \
+                     Split its master playlist instead, or pass --hls-chunk <SECONDS> to cut \
+                     it into chunks",
+                    input,
+                    media.segments.len()
+                )));
+            };
+            println!("Parsing HLS media playlist: {:?}", input.to_string());
+
+            let chunks = media.chunks(seconds);
+            println!(
+                "\nFound {} segments ({:.1}s), cut into {} chunks of up to {}s:",
+                media.segments.len(),
+                media.duration(),
+                chunks.len(),
+                seconds
+            );
+            let stem = input.name();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let path = PathBuf::from(format!("{}-{:03}.m3u8", stem, i + 1));
+                let note = format!(
+                    "{} segments, {:.1}s",
+                    chunk.segments.len(),
+                    chunk.duration()
+                );
+                println!("  {}: {}", path.display(), note);
+                let mut contents = Vec::new();
+                chunk.write(&mut contents)?;
+                files.push((path, note, contents));
+            }
+        }
     }
 
-    #[test]
-    fn test_parse_group_name_special_characters() {
-        let line = r#"#EXTINF:-1 group-title="Café & Música" tvg-id="channel6",Special"#;
-        assert_eq!(parse_group_name(line), Some("Café & Música".to_string()));
+    if args.dry_run {
+        println!("\nDry-run mode: No files written.");
+        return Ok(());
     }
 
-    #[test]
-    fn test_sanitize_filename_simple() {
-        assert_eq!(sanitize_filename("Sports"), "Sports");
+    let output_dir = args
+        .output
+        .as_ref()
+        .expect("--output is required without --extract");
+    fs::create_dir_all(output_dir)?;
+    // Relative URIs are resolved against the input's location, so point
+    // them there from the output directory
+    let base = uri_base(input, output_dir)?;
+    println!("\nWriting output files to: {:?}", output_dir);
+    for (path, note, contents) in files {
+        let path = args.compress.file_path(&path);
+        let contents = rebase_uris(&String::from_utf8_lossy(&contents), &base);
+        let mut writer = args
+            .compress
+            .writer(fs::File::create(output_dir.join(&path))?);
+        writer.write_all(contents.as_bytes())?;
+        writer.finish()?;
+        println!("  Created: {} ({})", path.display(), note);
     }
 
-    #[test]
-    fn test_sanitize_filename_with_spaces() {
-        assert_eq!(sanitize_filename("Kids & Family"), "Kids__Family");
-    }
+    println!("\nDone!");
+    Ok(())
+}
 
-    #[test]
-    fn test_sanitize_filename_non_ascii() {
-        assert_eq!(sanitize_filename("Café"), "Caf");
-        assert_eq!(sanitize_filename("Música"), "Msica");
-        assert_eq!(sanitize_filename("北京"), "");
+/// The relative URI reference that leads from `output_dir` to the directory
+/// of `input`, ending in `/`, or empty if they are the same directory.
+/// Standard input is taken to be in the current directory, and a zip
+/// member under its own path next to the archive.
+fn uri_base(input: &Input, output_dir: &Path) -> io::Result<String> {
+    let dir = match input.path.parent() {
+        Some(parent) if input.path.as_os_str() != STDIN && !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut to = dir.canonicalize()?;
+    if let Some(member) = &input.member {
+        to.extend(Path::new(member).parent());
     }
-
-    #[test]
-    fn test_sanitize_filename_special_chars() {
-        assert_eq!(sanitize_filename("Group/Name"), "GroupName");
-        assert_eq!(sanitize_filename("Group\\Name"), "GroupName");
-        assert_eq!(sanitize_filename("Group*Name"), "GroupName");
+    let from = output_dir.canonicalize()?;
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return Err(invalid(format!(
+            "{} and {} share no common directory for the relative URIs of {}",
+            to.display(),
+            from.display(),
+            input
+        )));
     }
+    let up = from.components().skip(common).map(|_| "..".to_string());
+    let down = to
+        .components()
+        .skip(common)
+        .map(|part| part.as_os_str().to_string_lossy().into_owned());
+    Ok(up.chain(down).map(|part| part + "/").collect())
+}
 
-    #[test]
-    fn test_sanitize_filename_leading_trailing_spaces() {
-        assert_eq!(sanitize_filename("  Sports  "), "Sports");
+/// The #EXT-X-STREAM-INF attribute HLS master playlists are split by
+/// without --variant-by.
+const DEFAULT_VARIANT_BY: &str = "RESOLUTION";
+
+/// Parses a positive --hls-chunk duration.
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => Err(format!(
+            "invalid duration {:?}; expected a number of seconds above 0",
+            s
+        )),
     }
+}
 
-    #[test]
-    fn test_sanitize_filename_dashes_and_underscores() {
-        assert_eq!(sanitize_filename("group-name"), "group-name");
-        assert_eq!(sanitize_filename("group_name"), "group_name");
-        assert_eq!(sanitize_filename("group-name_test"), "group-name_test");
+/// Parses a --header value of the form KEY=VALUE.
+fn parse_header_attribute(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value))
+            if !key.is_empty()
+                && !key.contains(|c: char| c.is_whitespace() || "\"',".contains(c)) =>
+        {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got {:?}", s)),
     }
+}
 
-    #[test]
-    fn test_parse_m3u_file_basic() {
-        let temp_dir = TempDir::new().unwrap();
-        let test_file = temp_dir.path().join("test.m3u");
-
-        let content = r#"#EXTM3U
-#EXTINF:-1 group-title="Sports" tvg-id="channel1",Sports Channel 1
-http://example.com/sports1.m3u8
-#EXTINF:-1 group-title="News" tvg-id="channel2",News Channel 1
-http://example.com/news1.m3u8
-#EXTINF:-1 group-title="Sports" tvg-id="channel3",Sports Channel 2
-http://example.com/sports2.m3u8
-"#;
-
-        fs::write(&test_file, content).unwrap();
+/// How many problems [`report_diagnostics`] lists before summarizing.
+const MAX_DIAGNOSTICS_SHOWN: usize = 20;
 
-        let channels = parse_m3u_file(&test_file).unwrap();
-        assert_eq!(channels.len(), 3);
-        assert_eq!(channels[0].group_name, "Sports");
-        assert_eq!(channels[0].url, "http://example.com/sports1.m3u8");
-        assert_eq!(channels[1].group_name, "News");
-        assert_eq!(channels[2].group_name, "Sports");
+/// Prints the problems found in the inputs to stderr, and fails under
+/// --strict if there are any.
+fn report_diagnostics(diagnostics: &[Diagnostic], strict: bool) -> io::Result<()> {
+    if diagnostics.is_empty() {
+        return Ok(());
     }
-
-    #[test]
-    fn test_parse_m3u_file_missing_group() {
-        let temp_dir = TempDir::new().unwrap();
-        let test_file = temp_dir.path().join("test.m3u");
-
-        let content = r#"#EXTM3U
-#EXTINF:-1 tvg-id="channel1",Channel Without Group
-http://example.com/channel1.m3u8
-"#;
-
-        fs::write(&test_file, content).unwrap();
-
-        let channels = parse_m3u_file(&test_file).unwrap();
-        assert_eq!(channels.len(), 1);
-        assert_eq!(channels[0].group_name, "Unknown");
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    eprintln!(
+        "\nFound {} problem(s) in the input ({} error(s), {} warning(s)):",
+        diagnostics.len(),
+        errors,
+        diagnostics.len() - errors
+    );
+    for diagnostic in diagnostics.iter().take(MAX_DIAGNOSTICS_SHOWN) {
+        eprintln!("  {}", diagnostic);
     }
-
-    #[test]
-    fn test_parse_m3u_file_empty() {
-        let temp_dir = TempDir::new().unwrap();
-        let test_file = temp_dir.path().join("test.m3u");
-
-        fs::write(&test_file, "#EXTM3U\n").unwrap();
-
-        let channels = parse_m3u_file(&test_file).unwrap();
-        assert_eq!(channels.len(), 0);
+    if diagnostics.len() > MAX_DIAGNOSTICS_SHOWN {
+        eprintln!(
+            "  ... and {} more",
+            diagnostics.len() - MAX_DIAGNOSTICS_SHOWN
+        );
     }
-
-    #[test]
-    fn test_write_group_file() {
-        let temp_dir = TempDir::new().unwrap();
-        let output_dir = temp_dir.path();
-
-        let channels = vec![
-            Channel {
-                extinf_line: r#"#EXTINF:-1 group-title="Sports" tvg-id="channel1",Sports Channel"#
-                    .to_string(),
-                url: "http://example.com/sports.m3u8".to_string(),
-                group_name: "Sports".to_string(),
-            },
-            Channel {
-                extinf_line:
-                    r#"#EXTINF:-1 group-title="Sports" tvg-id="channel2",Sports Channel 2"#
-                        .to_string(),
-                url: "http://example.com/sports2.m3u8".to_string(),
-                group_name: "Sports".to_string(),
-            },
-        ];
-
-        write_group_file(output_dir, "Sports", &channels).unwrap();
-
-        let output_file = output_dir.join("Sports.m3u");
-        assert!(output_file.exists());
-
-        let content = fs::read_to_string(&output_file).unwrap();
-        assert!(content.starts_with("#EXTM3U\n"));
-        assert!(content.contains("http://example.com/sports.m3u8"));
-        assert!(content.contains("http://example.com/sports2.m3u8"));
+    if strict {
+        return Err(invalid("--strict does not allow problems in the input"));
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_write_group_file_sanitized_name() {
-        let temp_dir = TempDir::new().unwrap();
-        let output_dir = temp_dir.path();
-
-        let channels = vec![Channel {
-            extinf_line: r#"#EXTINF:-1 group-title="Kids & Family" tvg-id="channel1",Kids Channel"#
-                .to_string(),
-            url: "http://example.com/kids.m3u8".to_string(),
-            group_name: "Kids & Family".to_string(),
-        }];
-
-        write_group_file(output_dir, "Kids & Family", &channels).unwrap();
-
-        let output_file = output_dir.join("Kids__Family.m3u");
-        assert!(output_file.exists());
-    }
-
-    #[test]
-    fn test_write_group_file_non_ascii_name() {
-        let temp_dir = TempDir::new().unwrap();
-        let output_dir = temp_dir.path();
-
-        let channels = vec![Channel {
-            extinf_line: r#"#EXTINF:-1 group-title="Café" tvg-id="channel1",Cafe Channel"#
-                .to_string(),
-            url: "http://example.com/cafe.m3u8".to_string(),
-            group_name: "Café".to_string(),
-        }];
-
-        write_group_file(output_dir, "Café", &channels).unwrap();
+/// The per-channel steps of a run, applied to entries in input order.
+struct Pipeline<'a> {
+    renames: &'a GroupRenames,
+    entry_filter: &'a EntryFilter,
+    duplicates: Option<&'a Duplicates>,
+    group_by: &'a GroupBy,
+    filter: &'a GroupFilter,
+    group_output: GroupOutput,
+    // Position among the entries meeting the --where conditions, as counted
+    // by the dedupe pass
+    index: usize,
+}
 
-        let output_file = output_dir.join("Caf.m3u");
-        assert!(output_file.exists());
+impl Pipeline<'_> {
+    /// Renames the group of `entry` and returns the groups it is written
+    /// to, or nothing if it is dropped.
+    fn keys(&mut self, entry: &mut Entry) -> Vec<GroupKey> {
+        self.renames.apply(entry);
+        if !self.entry_filter.matches(entry) {
+            return Vec::new();
+        }
+        let index = self.index;
+        self.index += 1;
+        if self.duplicates.is_some_and(|d| d.is_removed(index)) {
+            return Vec::new();
+        }
+        let mut keys = self.group_by.keys(entry);
+        keys.retain(|key| self.filter.matches(key));
+        if !keys.is_empty() {
+            self.group_output.apply(entry);
+        }
+        keys
     }
 }
@@ -0,0 +1,617 @@
+//! M3U parser.
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::extinf::{parse_extinf, parse_header, Attributes};
+use crate::playlist::{Entry, Playlist, EXTGRP};
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// Streaming iterator over the entries of an M3U playlist.
+///
+/// Lines are read one at a time, so memory use does not depend on the size
+/// of the input. Each entry starts at an `#EXTINF:` line; `#`-prefixed lines
+/// before the URL are kept as the entry's directives, and `#EXTGRP` lines
+/// in front of the `#EXTINF` line as its comments. An `#EXTINF` without a
+/// URL is dropped.
+///
+/// In a playlist without an `#EXTM3U` header, every line that is not a
+/// comment is a plain entry, see [`Entry::plain`], until the first
+/// `#EXTINF` line, so that playlists holding just one path or URL per line
+/// are read as well. With the header, such a line is stray.
+///
+/// Attributes of `#EXTM3U` header lines, such as `url-tvg`, are collected
+/// into [`Entries::header`]. Anomalies such as a dropped `#EXTINF`, a
+/// malformed one or a line that belongs to no entry are collected as
+/// [`Diagnostic`]s while reading.
+///
+/// In lossless mode, see [`Entries::with_lossless`], no line is lost: the
+/// lines between two entries become the comments of the second one, and
+/// every entry keeps its exact text for [`write_entry`](crate::write_entry).
+pub struct Entries<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    pending: Option<Pending>,
+    header: Attributes,
+    diagnostics: Vec<Diagnostic>,
+    lossless: bool,
+    // Lossless mode: the lines read since the last entry
+    leading: Vec<String>,
+    preamble: Vec<String>,
+    trailer: Vec<String>,
+    started: bool,
+    // Whether an #EXTINF line was read, after which bare lines are stray
+    extended: bool,
+    // Whether a line that is not blank was read
+    begun: bool,
+    // Number of the first line that is not blank, where the header belongs
+    first_line: usize,
+    // Whether the first line is not an #EXTM3U header, in which case bare
+    // lines before the first #EXTINF are plain entries
+    headerless: bool,
+    // `#EXTGRP` lines since the last entry, kept in front of the next one
+    groups: Vec<String>,
+}
+
+/// The entry being read: from its `#EXTINF` line up to the URL.
+struct Pending {
+    line_number: usize,
+    extinf_line: String,
+    directives: Vec<String>,
+    comments: Vec<String>,
+    // Lossless mode: the entry's lines so far, comments included, and the
+    // position of the EXTINF line among them
+    raw: Vec<String>,
+    raw_extinf: usize,
+}
+
+impl<R: BufRead> Entries<R> {
+    pub fn new(reader: R) -> Self {
+        Entries {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            pending: None,
+            header: Attributes::new(),
+            diagnostics: Vec::new(),
+            lossless: false,
+            leading: Vec::new(),
+            preamble: Vec::new(),
+            trailer: Vec::new(),
+            started: false,
+            extended: false,
+            begun: false,
+            first_line: 0,
+            headerless: false,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Keeps every line as read, line endings included: comments, blank
+    /// lines and unknown directives before an entry become its
+    /// [`Entry::comments`], the header lines at the very start its
+    /// [`Entries::preamble`] and lines after the last its
+    /// [`Entries::trailer`].
+    pub fn with_lossless(mut self, lossless: bool) -> Self {
+        self.lossless = lossless;
+        self
+    }
+}
+
+impl<R> Entries<R> {
+    /// The attributes of the `#EXTM3U` header lines read so far. If several
+    /// header lines set the same attribute, the first one wins.
+    pub fn header(&self) -> &Attributes {
+        &self.header
+    }
+
+    /// In lossless mode, the `#EXTM3U` and `#PLAYLIST:` lines at the start
+    /// of the playlist, with the blank lines among them, exactly as read.
+    /// Other lines before the first entry belong to that entry.
+    pub fn preamble(&self) -> &[String] {
+        &self.preamble
+    }
+
+    /// In lossless mode, the lines after the last entry exactly as read,
+    /// once the iterator is exhausted.
+    pub fn trailer(&self) -> &[String] {
+        &self.trailer
+    }
+
+    /// The problems found in the lines read so far.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns the problems found so far, leaving none behind.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn diagnose(&mut self, line: usize, severity: Severity, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::new(line, severity, message));
+    }
+
+    /// Before the first entry, moves the header lines at the start of the
+    /// lines read so far into the preamble.
+    fn take_preamble(&mut self) {
+        if self.started {
+            return;
+        }
+        let header = self
+            .leading
+            .iter()
+            .take_while(|line| {
+                let line = line.trim_start_matches('\u{feff}').trim();
+                line.is_empty() || line.starts_with("#EXTM3U") || line.starts_with("#PLAYLIST:")
+            })
+            .count();
+        self.preamble.extend(self.leading.drain(..header));
+    }
+
+    /// Keeps the line just read in lossless mode, as part of the pending
+    /// entry or of the lines before the next one.
+    fn keep_raw(&mut self) {
+        if !self.lossless {
+            return;
+        }
+        let raw = self.line.clone();
+        match self.pending.as_mut() {
+            Some(pending) => pending.raw.push(raw),
+            None => self.leading.push(raw),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Entries<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => {
+                    if let Some(pending) = self.pending.take() {
+                        self.diagnose(
+                            pending.line_number,
+                            Severity::Error,
+                            "#EXTINF at the end of the file has no URL; channel dropped",
+                        );
+                        self.leading = pending.raw;
+                    }
+                    self.trailer.append(&mut self.leading);
+                    return None;
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.line_number += 1;
+            let line_number = self.line_number;
+
+            // A UTF-8 byte order mark is not whitespace to `trim`
+            let line = self.line.trim_start_matches('\u{feff}').trim().to_string();
+            if line.is_empty() {
+                self.keep_raw();
+                continue;
+            }
+            if !self.begun {
+                self.begun = true;
+                self.first_line = line_number;
+                self.headerless = !line.starts_with("#EXTM3U");
+            }
+
+            if line.starts_with("#EXTINF:") {
+                if !self.extended && self.headerless {
+                    self.diagnose(self.first_line, Severity::Warning, "missing #EXTM3U header");
+                }
+                self.extended = true;
+                if let Some(orphan) = self.pending.take() {
+                    self.diagnose(
+                        orphan.line_number,
+                        Severity::Error,
+                        format!(
+                            "#EXTINF has no URL before the next #EXTINF on line {}; channel dropped",
+                            line_number
+                        ),
+                    );
+                    // Kept as comments of the next entry, as are its #EXTGRP
+                    // lines when not in lossless mode
+                    self.leading = orphan.raw;
+                    self.groups = orphan.comments;
+                    self.groups.extend(
+                        orphan
+                            .directives
+                            .into_iter()
+                            .filter(|line| line.starts_with(EXTGRP)),
+                    );
+                }
+                if let Err(e) = parse_extinf(&line) {
+                    self.diagnose(
+                        line_number,
+                        Severity::Warning,
+                        format!("malformed #EXTINF ({}); attributes ignored", e),
+                    );
+                }
+                self.take_preamble();
+                self.pending = Some(Pending {
+                    line_number,
+                    extinf_line: line,
+                    directives: Vec::new(),
+                    comments: std::mem::take(&mut self.groups),
+                    raw: std::mem::take(&mut self.leading),
+                    raw_extinf: 0,
+                });
+                self.keep_raw();
+                if let Some(pending) = self.pending.as_mut() {
+                    pending.raw_extinf = pending.raw.len().saturating_sub(1);
+                }
+                continue;
+            }
+
+            self.keep_raw();
+            if line.starts_with("#EXTM3U") {
+                match parse_header(&line) {
+                    Ok(attributes) => {
+                        for (key, value) in attributes.iter() {
+                            if !self.header.contains_key(key) {
+                                self.header.insert(key, value);
+                            }
+                        }
+                    }
+                    Err(e) => self.diagnose(
+                        line_number,
+                        Severity::Warning,
+                        format!("malformed #EXTM3U header ({}); attributes ignored", e),
+                    ),
+                }
+            } else if line.starts_with('#') {
+                match self.pending.as_mut() {
+                    Some(pending) => pending.directives.push(line),
+                    None if line.starts_with(EXTGRP) => self.groups.push(line),
+                    None => {}
+                }
+            } else if let Some(pending) = self.pending.take() {
+                let mut entry = Entry::new(pending.extinf_line, line);
+                entry.directives = pending.directives;
+                if self.lossless {
+                    entry.set_raw_lines(pending.raw, Some(pending.raw_extinf));
+                } else {
+                    entry.comments = pending.comments;
+                }
+                self.started = true;
+                return Some(Ok(entry));
+            } else if self.headerless && !self.extended {
+                let mut entry = Entry::plain(line);
+                if self.lossless {
+                    let url_line = self.leading.pop().unwrap_or_default();
+                    self.take_preamble();
+                    let mut raw = std::mem::take(&mut self.leading);
+                    raw.push(url_line);
+                    entry.set_raw_lines(raw, None);
+                } else {
+                    entry.comments = std::mem::take(&mut self.groups);
+                }
+                self.started = true;
+                return Some(Ok(entry));
+            } else {
+                self.diagnose(
+                    line_number,
+                    Severity::Warning,
+                    format!("line {:?} does not follow an #EXTINF; ignored", line),
+                );
+            }
+        }
+    }
+}
+
+/// Returns a streaming iterator over the entries read from `reader`.
+pub fn entries<R: BufRead>(reader: R) -> Entries<R> {
+    Entries::new(reader)
+}
+
+/// Opens the M3U file at `path` for streaming.
+pub fn entries_from_file(path: &Path) -> io::Result<Entries<BufReader<fs::File>>> {
+    Ok(Entries::new(BufReader::new(fs::File::open(path)?)))
+}
+
+/// Parses a whole M3U playlist from any buffered reader.
+pub fn parse_reader<R: BufRead>(reader: R) -> io::Result<Playlist> {
+    let mut iter = Entries::new(reader);
+    let entries = iter.by_ref().collect::<io::Result<_>>()?;
+    Ok(Playlist {
+        header: iter.header,
+        entries,
+        ..Playlist::default()
+    })
+}
+
+/// Parses a whole M3U playlist in lossless mode, keeping every line so that
+/// [`write_lossless`](crate::write_lossless) gives back the exact input.
+pub fn parse_lossless<R: BufRead>(reader: R) -> io::Result<Playlist> {
+    let mut iter = Entries::new(reader).with_lossless(true);
+    let entries = iter.by_ref().collect::<io::Result<_>>()?;
+    Ok(Playlist {
+        header: iter.header,
+        preamble: iter.preamble,
+        entries,
+        trailer: iter.trailer,
+    })
+}
+
+/// Parses an M3U playlist held in memory.
+pub fn parse_str(content: &str) -> Playlist {
+    parse_reader(content.as_bytes()).expect("reading from a string cannot fail")
+}
+
+/// Parses the M3U file at `path`.
+pub fn parse_file(path: &Path) -> io::Result<Playlist> {
+    let file = fs::File::open(path)?;
+    parse_reader(BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_file_basic() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.m3u");
+
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports" tvg-id="channel1",Sports Channel 1
+http://example.com/sports1.m3u8
+#EXTINF:-1 group-title="News" tvg-id="channel2",News Channel 1
+http://example.com/news1.m3u8
+#EXTINF:-1 group-title="Sports" tvg-id="channel3",Sports Channel 2
+http://example.com/sports2.m3u8
+"#;
+
+        fs::write(&test_file, content).unwrap();
+
+        let channels = parse_file(&test_file).unwrap().entries;
+        assert_eq!(channels.len(), 3);
+        assert_eq!(channels[0].group_name, "Sports");
+        assert_eq!(channels[0].url, "http://example.com/sports1.m3u8");
+        assert_eq!(channels[1].group_name, "News");
+        assert_eq!(channels[2].group_name, "Sports");
+        assert_eq!(channels[0].title, "Sports Channel 1");
+        assert_eq!(channels[0].duration, -1.0);
+        assert_eq!(channels[0].attributes.get("tvg-id"), Some("channel1"));
+    }
+
+    #[test]
+    fn test_parse_file_directives() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.m3u");
+
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Movies",Movie Channel
+#EXTVLCOPT:http-user-agent=Mozilla/5.0
+#KODIPROP:inputstream.adaptive.manifest_type=hls
+
+http://example.com/movie.m3u8
+#EXTINF:-1 group-title="News",News Channel
+http://example.com/news.m3u8
+"#;
+
+        fs::write(&test_file, content).unwrap();
+
+        let channels = parse_file(&test_file).unwrap().entries;
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].url, "http://example.com/movie.m3u8");
+        assert_eq!(
+            channels[0].directives,
+            vec![
+                "#EXTVLCOPT:http-user-agent=Mozilla/5.0",
+                "#KODIPROP:inputstream.adaptive.manifest_type=hls",
+            ]
+        );
+        assert!(channels[1].directives.is_empty());
+    }
+
+    #[test]
+    fn test_parse_file_extinf_without_url_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.m3u");
+
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Orphan",No URL
+#EXTINF:-1 group-title="News",News Channel
+http://example.com/news.m3u8
+#EXTINF:-1 group-title="Trailing",No URL either
+"#;
+
+        fs::write(&test_file, content).unwrap();
+
+        let channels = parse_file(&test_file).unwrap().entries;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].group_name, "News");
+    }
+
+    #[test]
+    fn test_parse_file_malformed_extinf_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.m3u");
+
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Broken,Channel Name
+http://example.com/broken.m3u8
+"#;
+
+        fs::write(&test_file, content).unwrap();
+
+        let channels = parse_file(&test_file).unwrap().entries;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].group_name, "Unknown");
+        assert_eq!(channels[0].url, "http://example.com/broken.m3u8");
+    }
+
+    #[test]
+    fn test_parse_file_missing_group() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.m3u");
+
+        let content = r#"#EXTM3U
+#EXTINF:-1 tvg-id="channel1",Channel Without Group
+http://example.com/channel1.m3u8
+"#;
+
+        fs::write(&test_file, content).unwrap();
+
+        let channels = parse_file(&test_file).unwrap().entries;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].group_name, "Unknown");
+    }
+
+    #[test]
+    fn test_parse_file_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.m3u");
+
+        fs::write(&test_file, "#EXTM3U\n").unwrap();
+
+        let channels = parse_file(&test_file).unwrap().entries;
+        assert_eq!(channels.len(), 0);
+    }
+
+    #[test]
+    fn test_entries_streams_one_at_a_time() {
+        let content = "#EXTM3U\n\
+                       #EXTINF:-1,First\n\
+                       http://example.com/1\n\
+                       #EXTINF:-1,Second\n\
+                       http://example.com/2\n";
+        let mut iter = entries(content.as_bytes());
+        assert_eq!(iter.next().unwrap().unwrap().title, "First");
+        assert_eq!(iter.next().unwrap().unwrap().title, "Second");
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_entries_invalid_utf8_is_error() {
+        let content: &[u8] = b"#EXTM3U\n#EXTINF:-1,Caf\xe9\nhttp://example.com/1\n";
+        let result: io::Result<Vec<Entry>> = entries(content).collect();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_entries_skip_byte_order_mark() {
+        let content = "\u{feff}#EXTINF:-1,First\nhttp://example.com/1\n";
+        let entries: Vec<Entry> = entries(content.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].extinf_line, "#EXTINF:-1,First");
+    }
+
+    #[test]
+    fn test_entries_plain() {
+        let content = "# My music\n\
+                       #EXTGRP:Rock\n\
+                       Music/Rock/song.mp3\n\
+                       \n\
+                       http://example.com/radio\n";
+        let mut iter = entries(content.as_bytes());
+        let plain: Vec<Entry> = iter.by_ref().map(Result::unwrap).collect();
+        assert_eq!(plain.len(), 2);
+        assert!(plain[0].is_plain());
+        assert_eq!(plain[0].title, "song");
+        assert_eq!(plain[0].comments, vec!["#EXTGRP:Rock"]);
+        assert_eq!(plain[1].url, "http://example.com/radio");
+        // A plain playlist needs no header
+        assert!(iter.diagnostics().is_empty());
+
+        // After the first #EXTINF, bare lines are stray
+        let content = "\n\
+                       http://example.com/plain\n\
+                       #EXTINF:-1,Extended\n\
+                       http://example.com/extended\n\
+                       http://example.com/stray\n";
+        let mut iter = entries(content.as_bytes());
+        let urls: Vec<String> = iter.by_ref().map(|entry| entry.unwrap().url).collect();
+        assert_eq!(
+            urls,
+            vec!["http://example.com/plain", "http://example.com/extended"]
+        );
+        // The missing header is reported at the first line that is not blank
+        let lines: Vec<usize> = iter.diagnostics().iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![2, 5]);
+
+        // With an #EXTM3U header, bare lines are always stray
+        let content = "#EXTM3U\n\
+                       <html>garbage\n\
+                       #EXTINF:-1,Extended\n\
+                       http://example.com/extended\n";
+        let mut iter = entries(content.as_bytes());
+        let urls: Vec<String> = iter.by_ref().map(|entry| entry.unwrap().url).collect();
+        assert_eq!(urls, vec!["http://example.com/extended"]);
+        assert_eq!(iter.diagnostics().len(), 1);
+        assert_eq!(iter.diagnostics()[0].line, 2);
+    }
+
+    #[test]
+    fn test_entries_diagnostics() {
+        let content = "#EXTINF:-1,No URL\n\
+                       #EXTINF:-1 group-title=\"Broken,Broken\n\
+                       http://example.com/broken\n\
+                       http://example.com/stray\n\
+                       #EXTINF:-1,Trailing\n";
+        let mut iter = entries(content.as_bytes());
+        assert_eq!(
+            iter.next().unwrap().unwrap().url,
+            "http://example.com/broken"
+        );
+        assert!(iter.next().is_none());
+
+        let diagnostics: Vec<(usize, Severity)> = iter
+            .diagnostics()
+            .iter()
+            .map(|d| (d.line, d.severity))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (1, Severity::Warning),
+                (1, Severity::Error),
+                (2, Severity::Warning),
+                (4, Severity::Warning),
+                (5, Severity::Error),
+            ]
+        );
+        assert_eq!(
+            iter.diagnostics()[1].to_string(),
+            "1: error: #EXTINF has no URL before the next #EXTINF on line 2; channel dropped"
+        );
+        assert_eq!(iter.take_diagnostics().len(), 5);
+        assert!(iter.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_parse_header_attributes() {
+        let playlist = parse_str(
+            "#EXTM3U url-tvg=\"http://example.com/epg.xml\" tvg-shift=\"1\"\n\
+             #EXTINF:-1,Title\n\
+             http://example.com/a\n\
+             #EXTM3U url-tvg=\"http://example.com/other.xml\" x-tvg-url=\"http://x\"\n",
+        );
+        let header: Vec<(&str, &str)> = playlist.header.iter().collect();
+        assert_eq!(
+            header,
+            vec![
+                ("url-tvg", "http://example.com/epg.xml"),
+                ("tvg-shift", "1"),
+                ("x-tvg-url", "http://x"),
+            ]
+        );
+        assert_eq!(playlist.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_str() {
+        let playlist = parse_str("#EXTM3U\n#EXTINF:-1,Title\nhttp://example.com/a\n");
+        assert_eq!(playlist.len(), 1);
+        assert_eq!(playlist.entries[0].title, "Title");
+    }
+}
@@ -0,0 +1,367 @@
+//! Output path planning and file name collision handling.
+
+use crate::filename::FilenameStrategy;
+use crate::group::{GroupKey, GroupStats};
+use crate::template::{NameContext, NameTemplate};
+use crate::writer::group_file_path;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What to do when different groups end up with the same file name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Keep the first group's name and append `-2`, `-3`, ... to the others.
+    #[default]
+    Suffix,
+    /// Write all colliding groups into the first group's file.
+    Merge,
+    /// Refuse to write anything.
+    Error,
+}
+
+impl CollisionPolicy {
+    pub const ALL: [CollisionPolicy; 3] = [
+        CollisionPolicy::Suffix,
+        CollisionPolicy::Merge,
+        CollisionPolicy::Error,
+    ];
+}
+
+impl fmt::Display for CollisionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CollisionPolicy::Suffix => "suffix",
+            CollisionPolicy::Merge => "merge",
+            CollisionPolicy::Error => "error",
+        })
+    }
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CollisionPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown collision policy {:?}; expected suffix, merge or error",
+                    s
+                )
+            })
+    }
+}
+
+/// Groups whose file names collided, and where each one was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    /// The file name the groups had in common.
+    pub path: PathBuf,
+    /// The colliding groups, in order of first appearance.
+    pub keys: Vec<GroupKey>,
+    /// The path each group was given, in the same order as `keys`.
+    pub resolved: Vec<PathBuf>,
+}
+
+/// Returned by [`OutputPlan::resolve`] under [`CollisionPolicy::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollisionError(pub Vec<Collision>);
+
+impl fmt::Display for CollisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} file name collision(s):", self.0.len())?;
+        for collision in &self.0 {
+            let keys: Vec<String> = collision.keys.iter().map(|k| k.to_string()).collect();
+            write!(f, "\n  {} <- {}", collision.path.display(), keys.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CollisionError {}
+
+/// The file every group is written to, decided before anything is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputPlan {
+    paths: HashMap<GroupKey, PathBuf>,
+    // Distinct paths in candidate order, with suffixed names right after
+    // the name they collided with
+    files: Vec<PathBuf>,
+    collisions: Vec<Collision>,
+}
+
+impl OutputPlan {
+    /// Resolves the candidate path of each group, given in output order,
+    /// applying `policy` to groups that share a path. The first candidate
+    /// keeps the shared name under [`CollisionPolicy::Suffix`]. Paths are
+    /// compared case-insensitively so that the output is also safe on
+    /// case-insensitive file systems.
+    pub fn resolve<I>(candidates: I, policy: CollisionPolicy) -> Result<Self, CollisionError>
+    where
+        I: IntoIterator<Item = (GroupKey, PathBuf)>,
+    {
+        // Candidates sharing a normalized path, in order of first appearance
+        let mut buckets: Vec<(PathBuf, Vec<GroupKey>)> = Vec::new();
+        let mut bucket_index: HashMap<String, usize> = HashMap::new();
+        for (key, path) in candidates {
+            match bucket_index.get(&normalize(&path)) {
+                Some(&i) => buckets[i].1.push(key),
+                None => {
+                    bucket_index.insert(normalize(&path), buckets.len());
+                    buckets.push((path, vec![key]));
+                }
+            }
+        }
+
+        let mut taken: HashSet<String> = bucket_index.into_keys().collect();
+        let mut plan = OutputPlan::default();
+        for (path, keys) in buckets {
+            if keys.len() == 1 {
+                plan.files.push(path.clone());
+                plan.paths.insert(keys[0].clone(), path);
+                continue;
+            }
+
+            let resolved: Vec<PathBuf> = match policy {
+                CollisionPolicy::Merge | CollisionPolicy::Error => vec![path.clone(); keys.len()],
+                CollisionPolicy::Suffix => {
+                    let mut resolved = vec![path.clone()];
+                    let mut n = 2;
+                    while resolved.len() < keys.len() {
+                        let candidate = with_suffix(&path, n);
+                        if taken.insert(normalize(&candidate)) {
+                            resolved.push(candidate);
+                        }
+                        n += 1;
+                    }
+                    resolved
+                }
+            };
+            for (key, resolved_path) in keys.iter().zip(&resolved) {
+                if !plan.files.contains(resolved_path) {
+                    plan.files.push(resolved_path.clone());
+                }
+                plan.paths.insert(key.clone(), resolved_path.clone());
+            }
+            plan.collisions.push(Collision {
+                path,
+                keys,
+                resolved,
+            });
+        }
+
+        if policy == CollisionPolicy::Error && !plan.collisions.is_empty() {
+            return Err(CollisionError(plan.collisions));
+        }
+        Ok(plan)
+    }
+
+    /// The file for `key`, relative to the output directory.
+    pub fn path(&self, key: &GroupKey) -> Option<&Path> {
+        self.paths.get(key).map(PathBuf::as_path)
+    }
+
+    /// Every distinct output file, in output order.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn paths(&self) -> &HashMap<GroupKey, PathBuf> {
+        &self.paths
+    }
+
+    pub fn collisions(&self) -> &[Collision] {
+        &self.collisions
+    }
+
+    pub fn into_paths(self) -> HashMap<GroupKey, PathBuf> {
+        self.paths
+    }
+}
+
+/// Computes the unresolved path of every group counted in `stats`, in order
+/// of first appearance, using `template` if given or the default
+/// `<group>.m3u`. `{index}` is the position in that order, so neither
+/// `--sort` nor filtering changes the file names, nor which of several
+/// colliding groups keeps the shared name. With `aggregates`, the aggregate
+/// playlist of every directory level is included before its first group.
+pub fn candidate_paths(
+    stats: &GroupStats,
+    template: Option<&NameTemplate>,
+    strategy: FilenameStrategy,
+    stem: &str,
+    aggregates: bool,
+) -> Vec<(GroupKey, PathBuf)> {
+    let mut candidates = Vec::with_capacity(stats.order.len());
+    let mut seen_ancestors = HashSet::new();
+
+    for key in stats.first_seen_order() {
+        if aggregates {
+            for ancestor in key.ancestors() {
+                if seen_ancestors.insert(ancestor.clone()) {
+                    let path = group_file_path(&ancestor, strategy);
+                    candidates.push((ancestor, path));
+                }
+            }
+        }
+
+        let path = match template {
+            Some(template) => template.render_path(&NameContext {
+                key,
+                count: stats.counts[key],
+                index: stats.first_seen[key],
+                stem,
+            }),
+            None => group_file_path(key, strategy),
+        };
+        candidates.push((key.clone(), path));
+    }
+    candidates
+}
+
+fn normalize(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// `dir/Name.m3u` with `n = 2` gives `dir/Name-2.m3u`.
+fn with_suffix(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::GroupSort;
+
+    fn key(name: &str) -> GroupKey {
+        GroupKey::from(name)
+    }
+
+    fn colliding_candidates() -> Vec<(GroupKey, PathBuf)> {
+        let mut stats = GroupStats::default();
+        for name in [
+            "Kids & Family",
+            "News",
+            "Kids  Family",
+            "Kids__Family-2",
+            "news",
+        ] {
+            stats.record(&[key(name)]);
+        }
+        candidate_paths(&stats, None, FilenameStrategy::Ascii, "input", false)
+    }
+
+    #[test]
+    fn test_resolve_suffix() {
+        let plan = OutputPlan::resolve(colliding_candidates(), CollisionPolicy::Suffix).unwrap();
+
+        assert_eq!(
+            plan.path(&key("Kids & Family")),
+            Some(Path::new("Kids__Family.m3u"))
+        );
+        // "-2" is already taken by another group, so the next free suffix is used
+        assert_eq!(
+            plan.path(&key("Kids  Family")),
+            Some(Path::new("Kids__Family-3.m3u"))
+        );
+        assert_eq!(
+            plan.path(&key("Kids__Family-2")),
+            Some(Path::new("Kids__Family-2.m3u"))
+        );
+        assert_eq!(plan.path(&key("news")), Some(Path::new("News-2.m3u")));
+        assert_eq!(
+            plan.files(),
+            [
+                "Kids__Family.m3u",
+                "Kids__Family-3.m3u",
+                "News.m3u",
+                "News-2.m3u",
+                "Kids__Family-2.m3u",
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(plan.collisions().len(), 2);
+        assert_eq!(
+            plan.collisions()[0].keys,
+            vec![key("Kids & Family"), key("Kids  Family")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_merge() {
+        let plan = OutputPlan::resolve(colliding_candidates(), CollisionPolicy::Merge).unwrap();
+
+        assert_eq!(
+            plan.path(&key("Kids  Family")),
+            Some(Path::new("Kids__Family.m3u"))
+        );
+        assert_eq!(plan.path(&key("news")), Some(Path::new("News.m3u")));
+        assert_eq!(plan.collisions().len(), 2);
+    }
+
+    #[test]
+    fn test_resolve_error() {
+        let err = OutputPlan::resolve(colliding_candidates(), CollisionPolicy::Error).unwrap_err();
+
+        assert_eq!(err.0.len(), 2);
+        let message = err.to_string();
+        assert!(message.contains("Kids__Family.m3u <- Kids & Family, Kids  Family"));
+    }
+
+    #[test]
+    fn test_candidate_paths_template_and_aggregates() {
+        let mut stats = GroupStats::default();
+        let uk_news = GroupKey::new(vec!["UK".to_string(), "News".to_string()]);
+        let uk_sports = GroupKey::new(vec!["UK".to_string(), "Sports".to_string()]);
+        stats.record(std::slice::from_ref(&uk_news));
+        stats.record(std::slice::from_ref(&uk_sports));
+        stats.record(std::slice::from_ref(&uk_news));
+
+        let template = NameTemplate::parse("{index:02}-{group}-{count}.m3u", &[]).unwrap();
+        let candidates =
+            candidate_paths(&stats, Some(&template), FilenameStrategy::Ascii, "in", true);
+
+        assert_eq!(
+            candidates,
+            vec![
+                (key("UK"), PathBuf::from("UK.m3u")),
+                (uk_news, PathBuf::from("UK").join("01-News-2.m3u")),
+                (uk_sports, PathBuf::from("UK").join("02-Sports-1.m3u")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_candidate_paths_index_is_first_seen() {
+        let mut stats = GroupStats::default();
+        for name in ["Zeta", "Alpha", "Beta"] {
+            stats.record(&[key(name)]);
+        }
+        stats.sort(GroupSort::Name, false);
+        stats.retain(|key| key.name() != "Alpha");
+
+        let template = NameTemplate::parse("{index}-{group}.m3u", &[]).unwrap();
+        let candidates = candidate_paths(
+            &stats,
+            Some(&template),
+            FilenameStrategy::Ascii,
+            "in",
+            false,
+        );
+        assert_eq!(
+            candidates,
+            vec![
+                (key("Zeta"), PathBuf::from("1-Zeta.m3u")),
+                (key("Beta"), PathBuf::from("3-Beta.m3u")),
+            ]
+        );
+    }
+}
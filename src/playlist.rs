@@ -0,0 +1,335 @@
+//! In-memory playlist model.
+
+use crate::extinf::{
+    parse_extinf, remove_extinf_attribute, set_extinf_attribute, Attributes, Extinf,
+};
+
+/// Group assigned to entries without a `group-title` attribute.
+pub const DEFAULT_GROUP: &str = "Unknown";
+
+/// Prefix of the directive naming an entry's group, as in `#EXTGRP:Movies`.
+pub const EXTGRP: &str = "#EXTGRP:";
+
+/// A single playlist entry: its `#EXTINF` line, directives and URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The `#EXTINF` line exactly as read, written back unchanged. Empty
+    /// for a plain entry, see [`Entry::plain`].
+    pub extinf_line: String,
+    pub url: String,
+    pub group_name: String,
+    pub duration: f64,
+    pub attributes: Attributes,
+    pub title: String,
+    /// Directive lines (`#EXTVLCOPT`, `#KODIPROP`, ...) found between the
+    /// `#EXTINF` line and the URL, in their original order.
+    pub directives: Vec<String>,
+    /// Comments, unknown directives and other lines before the `#EXTINF`
+    /// line, kept by lossless parsing. `#EXTGRP` lines are always kept.
+    pub comments: Vec<String>,
+    /// The exact lines the entry was read from, kept by lossless parsing.
+    pub(crate) raw: Option<RawEntry>,
+}
+
+/// The lines of an entry exactly as read, line endings included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RawEntry {
+    /// Comments, the `#EXTINF` line, directives and the URL line.
+    pub(crate) lines: Vec<String>,
+    /// Position of the `#EXTINF` line in `lines`, if the entry has one.
+    pub(crate) extinf: Option<usize>,
+    /// The URL as parsed, to tell whether the entry was changed since it
+    /// was read.
+    url: String,
+    /// The comments as parsed.
+    comments: Vec<String>,
+    /// The directives as parsed.
+    directives: Vec<String>,
+}
+
+impl RawEntry {
+    /// Whether the comments, directives and URL of `entry` are still the
+    /// ones read. The `#EXTINF` line may have changed, but a plain entry
+    /// must not have gained one.
+    pub(crate) fn matches(&self, entry: &Entry) -> bool {
+        (self.extinf.is_some() || entry.is_plain())
+            && self.url == entry.url
+            && self.comments == entry.comments
+            && self.directives == entry.directives
+    }
+}
+
+impl Entry {
+    pub fn new(extinf_line: String, url: String) -> Self {
+        // Keep malformed entries: they still have a URL worth splitting out,
+        // they just carry no attributes.
+        let extinf = parse_extinf(&extinf_line).unwrap_or_else(|_| Extinf {
+            duration: -1.0,
+            attributes: Attributes::new(),
+            title: extinf_line
+                .split_once(',')
+                .map(|(_, title)| title.trim().to_string())
+                .unwrap_or_default(),
+        });
+        let group_name = extinf
+            .attributes
+            .get("group-title")
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+            .unwrap_or(DEFAULT_GROUP)
+            .to_string();
+
+        Entry {
+            extinf_line,
+            url,
+            group_name,
+            duration: extinf.duration,
+            attributes: extinf.attributes,
+            title: extinf.title,
+            directives: Vec::new(),
+            comments: Vec::new(),
+            raw: None,
+        }
+    }
+
+    /// A plain entry: a path or URL on a line of its own, without an
+    /// `#EXTINF` line. Its title is the file name without extension, and it
+    /// belongs to [`DEFAULT_GROUP`].
+    pub fn plain(url: String) -> Self {
+        let (_, path) = split_location(&url);
+        let name = path.rsplit(['/', '\\']).next().unwrap_or_default();
+        let title = match name.rsplit_once('.') {
+            Some((stem, _)) if !stem.is_empty() => stem,
+            _ => name,
+        };
+        let title = if title.is_empty() {
+            url.as_str()
+        } else {
+            title
+        };
+
+        Entry {
+            extinf_line: String::new(),
+            group_name: DEFAULT_GROUP.to_string(),
+            duration: -1.0,
+            attributes: Attributes::new(),
+            title: title.to_string(),
+            directives: Vec::new(),
+            comments: Vec::new(),
+            raw: None,
+            url,
+        }
+    }
+
+    /// Whether the entry has no `#EXTINF` line.
+    pub fn is_plain(&self) -> bool {
+        self.extinf_line.is_empty()
+    }
+
+    /// Keeps the exact lines the entry was read from, with the `#EXTINF`
+    /// line at `extinf`, and the non-blank lines before it, or before the
+    /// URL of a plain entry, as comments.
+    pub(crate) fn set_raw_lines(&mut self, lines: Vec<String>, extinf: Option<usize>) {
+        let end = extinf.unwrap_or(lines.len().saturating_sub(1));
+        self.comments = lines[..end]
+            .iter()
+            .map(|line| line.trim_start_matches('\u{feff}').trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        self.raw = Some(RawEntry {
+            lines,
+            extinf,
+            url: self.url.clone(),
+            comments: self.comments.clone(),
+            directives: self.directives.clone(),
+        });
+    }
+
+    /// Sets attribute `key` to `value`, in the `#EXTINF` line as well so
+    /// that it is written out. A plain entry gets an `#EXTINF` line with
+    /// its title for this. Setting `group-title` also updates `group_name`.
+    pub fn set_attribute(&mut self, key: &str, value: &str) {
+        if self.is_plain() {
+            self.extinf_line = format!("#EXTINF:-1,{}", self.title);
+        }
+        // A malformed line is written back as it was
+        if let Ok(line) = set_extinf_attribute(&self.extinf_line, key, value) {
+            self.extinf_line = line;
+        }
+        if key.eq_ignore_ascii_case("group-title") {
+            self.group_name = match value.trim() {
+                "" => DEFAULT_GROUP.to_string(),
+                name => name.to_string(),
+            };
+        }
+        self.attributes.insert(key, value);
+    }
+
+    /// Removes attribute `key`, from the `#EXTINF` line as well. Removing
+    /// `group-title` puts the entry into [`DEFAULT_GROUP`].
+    pub fn remove_attribute(&mut self, key: &str) {
+        if let Ok(line) = remove_extinf_attribute(&self.extinf_line, key) {
+            self.extinf_line = line;
+        }
+        if key.eq_ignore_ascii_case("group-title") {
+            self.group_name = DEFAULT_GROUP.to_string();
+        }
+        self.attributes.remove(key);
+    }
+
+    /// The group named by an `#EXTGRP:` line among the directives or, in
+    /// front of the `#EXTINF` line, the comments.
+    pub fn extgrp(&self) -> Option<&str> {
+        self.directives
+            .iter()
+            .chain(&self.comments)
+            .find_map(|line| line.strip_prefix(EXTGRP))
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+    }
+}
+
+/// Splits a location into the host of a URL, if it is one, and the path:
+/// `http://user@Example.com:80/live/a.ts?token=1` into `Example.com` and
+/// `/live/a.ts`. A local path is returned whole.
+pub(crate) fn split_location(location: &str) -> (Option<&str>, &str) {
+    let Some((_, rest)) = location.split_once("://") else {
+        return (None, location);
+    };
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = match host.strip_prefix('[') {
+        // An IPv6 address such as [::1]:8080
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    (Some(host).filter(|host| !host.is_empty()), path)
+}
+
+/// An ordered list of entries read from one M3U file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Playlist {
+    /// Attributes of the `#EXTM3U` header line, such as `url-tvg`.
+    pub header: Attributes,
+    /// The `#EXTM3U` and `#PLAYLIST:` lines at the start exactly as read,
+    /// kept by lossless parsing. Comments after them belong to the first
+    /// entry.
+    pub preamble: Vec<String>,
+    pub entries: Vec<Entry>,
+    /// The lines after the last entry exactly as read, kept by lossless
+    /// parsing.
+    pub trailer: Vec<String>,
+}
+
+impl Playlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Entry> {
+        self.entries.iter()
+    }
+}
+
+impl IntoIterator for Playlist {
+    type Item = Entry;
+    type IntoIter = std::vec::IntoIter<Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Playlist {
+    type Item = &'a Entry;
+    type IntoIter = std::slice::Iter<'a, Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_new_parses_extinf() {
+        let entry = Entry::new(
+            r#"#EXTINF:-1 tvg-id="channel1" group-title="Sports",Sports Channel"#.to_string(),
+            "http://example.com/sports.m3u8".to_string(),
+        );
+        assert_eq!(entry.group_name, "Sports");
+        assert_eq!(entry.title, "Sports Channel");
+        assert_eq!(entry.duration, -1.0);
+        assert_eq!(entry.attributes.get("tvg-id"), Some("channel1"));
+    }
+
+    #[test]
+    fn test_entry_set_attribute() {
+        let mut entry = Entry::new(
+            r#"#EXTINF:-1 group-title="Sports",Sports Channel"#.to_string(),
+            "http://example.com/sports.m3u8".to_string(),
+        );
+        entry.set_attribute("source", "provider-a");
+        entry.set_attribute("group-title", "Sport");
+        assert_eq!(
+            entry.extinf_line,
+            r#"#EXTINF:-1 group-title="Sport" source="provider-a",Sports Channel"#
+        );
+        assert_eq!(entry.attributes.get("source"), Some("provider-a"));
+        assert_eq!(entry.group_name, "Sport");
+    }
+
+    #[test]
+    fn test_entry_plain() {
+        let mut entry = Entry::plain("Music/Artist - Song.mp3".to_string());
+        assert!(entry.is_plain());
+        assert_eq!(entry.title, "Artist - Song");
+        assert_eq!(entry.group_name, DEFAULT_GROUP);
+
+        entry.set_attribute("source", "disk");
+        assert!(!entry.is_plain());
+        assert_eq!(
+            entry.extinf_line,
+            r#"#EXTINF:-1 source="disk",Artist - Song"#
+        );
+
+        let entry = Entry::plain("http://example.com/".to_string());
+        assert_eq!(entry.title, "http://example.com/");
+    }
+
+    #[test]
+    fn test_split_location() {
+        assert_eq!(
+            split_location("http://user@Example.com:80/live/a.ts?token=1"),
+            (Some("Example.com"), "/live/a.ts")
+        );
+        assert_eq!(split_location("rtp://[::1]:5004"), (Some("::1"), ""));
+        assert_eq!(
+            split_location("C:\\Music\\a.mp3"),
+            (None, "C:\\Music\\a.mp3")
+        );
+    }
+
+    #[test]
+    fn test_entry_new_malformed_extinf() {
+        let entry = Entry::new(
+            r#"#EXTINF:-1 group-title="Broken,Channel Name"#.to_string(),
+            "http://example.com/broken.m3u8".to_string(),
+        );
+        assert_eq!(entry.group_name, DEFAULT_GROUP);
+        assert!(entry.attributes.is_empty());
+        assert_eq!(entry.title, "Channel Name");
+    }
+}
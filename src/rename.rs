@@ -0,0 +1,337 @@
+//! Group renames loaded from a TOML or CSV mapping file.
+
+use crate::filter::Pattern;
+use crate::playlist::{Entry, DEFAULT_GROUP};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Error returned when a mapping file cannot be read or parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameError(String);
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid mapping file: {}", self.0)
+    }
+}
+
+impl std::error::Error for RenameError {}
+
+/// Renames groups matching a pattern.
+///
+/// The pattern has the same syntax as a group filter: an exact name, a glob
+/// or a `re:` regular expression. For regular expressions, `$1` or
+/// `${name}` in the new name are replaced with the captured text.
+#[derive(Debug, Clone)]
+pub struct RenameRule {
+    pattern: Pattern,
+    to: String,
+}
+
+impl RenameRule {
+    pub fn new(from: &str, to: impl Into<String>) -> Result<Self, RenameError> {
+        let pattern = Pattern::new(from, false).map_err(|e| RenameError(e.to_string()))?;
+        Ok(RenameRule {
+            pattern,
+            to: to.into(),
+        })
+    }
+
+    /// The new name for `name`, if the rule applies to it.
+    pub fn rename(&self, name: &str) -> Option<String> {
+        let captures = self.pattern.regex().captures(name)?;
+        if !self.pattern.is_regex() {
+            return Some(self.to.clone());
+        }
+        let mut renamed = String::new();
+        captures.expand(&self.to, &mut renamed);
+        Some(renamed)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingFile {
+    #[serde(default)]
+    rename: Vec<MappingRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingRule {
+    from: String,
+    to: String,
+}
+
+/// An ordered list of rename rules, where the first matching rule wins.
+#[derive(Debug, Clone, Default)]
+pub struct GroupRenames {
+    rules: Vec<RenameRule>,
+    rewrite_extinf: bool,
+}
+
+impl GroupRenames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule, tried after the existing ones.
+    pub fn with_rule(mut self, rule: RenameRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Also writes the new `group-title` into each renamed entry's
+    /// `#EXTINF` line, so the output files carry the new name.
+    pub fn with_rewrite_extinf(mut self, rewrite: bool) -> Self {
+        self.rewrite_extinf = rewrite;
+        self
+    }
+
+    /// Parses rules from TOML:
+    ///
+    /// ```toml
+    /// [[rename]]
+    /// from = "UK | Sports"
+    /// to = "Sports-UK"
+    /// ```
+    pub fn from_toml_str(toml: &str) -> Result<Self, RenameError> {
+        let file: MappingFile = toml::from_str(toml).map_err(|e| RenameError(e.to_string()))?;
+        file.rename
+            .into_iter()
+            .try_fold(GroupRenames::new(), |renames, rule| {
+                Ok(renames.with_rule(RenameRule::new(&rule.from, rule.to)?))
+            })
+    }
+
+    /// Parses rules from CSV with a `from,to` pair per line. A `from,to`
+    /// header, blank lines and lines starting with `#` are skipped. Fields
+    /// containing commas can be double-quoted, with `""` for a quote.
+    pub fn from_csv_str(csv: &str) -> Result<Self, RenameError> {
+        let mut renames = GroupRenames::new();
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = parse_csv_line(line)
+                .ok_or_else(|| RenameError(format!("line {}: unclosed quote", number + 1)))?;
+            let [from, to] = fields.as_slice() else {
+                return Err(RenameError(format!(
+                    "line {}: expected 2 fields (from,to), found {}",
+                    number + 1,
+                    fields.len()
+                )));
+            };
+            if renames.rules.is_empty()
+                && from.eq_ignore_ascii_case("from")
+                && to.eq_ignore_ascii_case("to")
+            {
+                continue;
+            }
+            renames = renames.with_rule(
+                RenameRule::new(from, to.as_str())
+                    .map_err(|e| RenameError(format!("line {}: {}", number + 1, e.0)))?,
+            );
+        }
+        Ok(renames)
+    }
+
+    /// Reads rules from a `.toml` or `.csv` file.
+    pub fn from_file(path: &Path) -> Result<Self, RenameError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| RenameError(format!("{}: {}", path.display(), e)))?;
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        let parsed = match extension.as_deref() {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("csv") => Self::from_csv_str(&content),
+            _ => {
+                return Err(RenameError(format!(
+                    "{}: expected a .toml or .csv file",
+                    path.display()
+                )))
+            }
+        };
+        parsed.map_err(|e| RenameError(format!("{}: {}", path.display(), e.0)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The new name for group `name`, from the first rule that applies.
+    pub fn rename(&self, name: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| rule.rename(name))
+    }
+
+    /// Renames the group of `entry` by rewriting its `group_name` and
+    /// `group-title` attribute. Returns whether a rule applied.
+    pub fn apply(&self, entry: &mut Entry) -> bool {
+        let Some(renamed) = self.rename(&entry.group_name) else {
+            return false;
+        };
+        if self.rewrite_extinf {
+            entry.set_attribute("group-title", &renamed);
+            return true;
+        }
+        entry.group_name = match renamed.trim() {
+            "" => DEFAULT_GROUP.to_string(),
+            name => name.to_string(),
+        };
+        entry.attributes.insert("group-title", renamed);
+        true
+    }
+}
+
+/// Splits one CSV line into trimmed fields, or `None` if a quote is not
+/// closed.
+fn parse_csv_line(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| *c == ' ' || *c == '\t') {
+            chars.next();
+        }
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => break,
+                    c => field.push(c),
+                }
+            }
+            // Skip anything between the closing quote and the next comma
+            while chars.peek().is_some_and(|c| *c != ',') {
+                chars.next();
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                field.push(c);
+                chars.next();
+            }
+            field = field.trim().to_string();
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Some(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(extinf_line: &str) -> Entry {
+        Entry::new(extinf_line.to_string(), "http://example.com/1".to_string())
+    }
+
+    #[test]
+    fn test_rename_rules_first_match_wins() {
+        let renames = GroupRenames::new()
+            .with_rule(RenameRule::new("UK | Sports", "Sports-UK").unwrap())
+            .with_rule(
+                RenameRule::new(r"re:(?i)^(uk\s*[:|]\s*sports|sports uk)$", "Sports-UK").unwrap(),
+            )
+            .with_rule(RenameRule::new(r"re:(?i)^(\w+) news$", "${1}-News").unwrap())
+            .with_rule(RenameRule::new("Sports*", "Other").unwrap());
+
+        assert_eq!(renames.rename("UK | Sports"), Some("Sports-UK".to_string()));
+        // Case-insensitive rules map every spelling to the same name
+        assert_eq!(renames.rename("UK: SPORTS"), Some("Sports-UK".to_string()));
+        assert_eq!(renames.rename("uk|sports"), Some("Sports-UK".to_string()));
+        assert_eq!(renames.rename("Sports UK"), Some("Sports-UK".to_string()));
+        assert_eq!(renames.rename("UK News"), Some("UK-News".to_string()));
+        assert_eq!(renames.rename("Sports HD"), Some("Other".to_string()));
+        assert_eq!(renames.rename("News"), None);
+    }
+
+    #[test]
+    fn test_from_toml_str() {
+        let renames = GroupRenames::from_toml_str(
+            r#"
+[[rename]]
+from = "UK | Sports"
+to = "Sports-UK"
+
+[[rename]]
+from = 're:(?i)uk:\s*sports'
+to = "Sports-UK"
+"#,
+        )
+        .unwrap();
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames.rename("uk: Sports"), Some("Sports-UK".to_string()));
+
+        assert!(GroupRenames::from_toml_str("[[rename]]\nfrom = \"a\"\n").is_err());
+        assert!(GroupRenames::from_toml_str("[[rename]]\nfrom = \"re:(\"\nto = \"b\"\n").is_err());
+    }
+
+    #[test]
+    fn test_from_csv_str() {
+        let renames = GroupRenames::from_csv_str(
+            r#"from,to
+# Provider A
+UK | Sports, Sports-UK
+"UK, Sports","Sports ""UK"""
+
+re:(?i)sports uk,Sports-UK
+"#,
+        )
+        .unwrap();
+        assert_eq!(renames.len(), 3);
+        assert_eq!(renames.rename("UK | Sports"), Some("Sports-UK".to_string()));
+        assert_eq!(
+            renames.rename("UK, Sports"),
+            Some(r#"Sports "UK""#.to_string())
+        );
+        assert_eq!(renames.rename("SPORTS UK"), Some("Sports-UK".to_string()));
+
+        let err = GroupRenames::from_csv_str("a,b\nc\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(GroupRenames::from_csv_str("\"a,b\n").is_err());
+    }
+
+    #[test]
+    fn test_apply_to_entry() {
+        let renames =
+            GroupRenames::new().with_rule(RenameRule::new("UK | Sports", "Sports-UK").unwrap());
+        let line = r#"#EXTINF:-1 tvg-id="sky" group-title="UK | Sports",Sky Sports"#;
+
+        let mut kept = entry(line);
+        assert!(renames.apply(&mut kept));
+        assert_eq!(kept.group_name, "Sports-UK");
+        assert_eq!(kept.attributes.get("group-title"), Some("Sports-UK"));
+        assert_eq!(kept.extinf_line, line);
+
+        let mut rewritten = entry(line);
+        assert!(renames
+            .clone()
+            .with_rewrite_extinf(true)
+            .apply(&mut rewritten));
+        assert_eq!(
+            rewritten.extinf_line,
+            r#"#EXTINF:-1 tvg-id="sky" group-title="Sports-UK",Sky Sports"#
+        );
+
+        let mut other = entry(r#"#EXTINF:-1 group-title="News",BBC"#);
+        assert!(!renames.apply(&mut other));
+        assert_eq!(other.group_name, "News");
+    }
+}
@@ -0,0 +1,483 @@
+//! Streaming split pipeline.
+
+use crate::compress::{CompressedWriter, OutputCompression};
+use crate::extinf::Attributes;
+use crate::filename::FilenameStrategy;
+use crate::group::{GroupBy, GroupKey};
+use crate::parser::entries;
+use crate::playlist::Entry;
+use crate::writer::{group_file_path, write_entry, write_header_with_attributes};
+use std::collections::hash_map::{self, HashMap};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+/// A file produced by a [`Splitter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrittenGroup {
+    /// The groups written to this file, in order of first write. More than
+    /// one when colliding groups were merged.
+    pub keys: Vec<GroupKey>,
+    pub path: PathBuf,
+    pub count: usize,
+    /// Whether this is an aggregate playlist for a directory level rather
+    /// than a group of its own.
+    pub aggregate: bool,
+}
+
+/// How many files a [`Splitter`] keeps open at once by default, well under
+/// the common limit of 1024 descriptors per process.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 256;
+
+struct OutputFile {
+    keys: Vec<GroupKey>,
+    // None while the file is closed to stay under the open file cap
+    writer: Option<CompressedWriter<fs::File>>,
+    count: usize,
+    aggregate: bool,
+}
+
+/// Writes entries to per-group files as they arrive.
+///
+/// A group's file is created the first time an entry for it is written, so
+/// memory is bounded by the number of groups rather than the number of
+/// entries. At most [`Splitter::with_max_open_files`] files are open at
+/// once: when another one is needed, the least recently written file is
+/// closed and later reopened in append mode. A gzip file reopened this way
+/// gets a new gzip member, which decoders read as one stream.
+pub struct Splitter {
+    output_dir: PathBuf,
+    strategy: FilenameStrategy,
+    aggregates: bool,
+    compression: OutputCompression,
+    header: Attributes,
+    max_open: usize,
+    paths: HashMap<GroupKey, PathBuf>,
+    files: HashMap<PathBuf, OutputFile>,
+    // Creation order of `files`, so results come back in a stable order
+    order: Vec<PathBuf>,
+    // Files with an open writer, least recently written first
+    open: VecDeque<PathBuf>,
+}
+
+impl Splitter {
+    /// Creates a splitter writing into `output_dir`, which must exist.
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Splitter {
+            output_dir: output_dir.into(),
+            strategy: FilenameStrategy::default(),
+            aggregates: false,
+            compression: OutputCompression::default(),
+            header: Attributes::new(),
+            max_open: DEFAULT_MAX_OPEN_FILES,
+            paths: HashMap::new(),
+            files: HashMap::new(),
+            order: Vec::new(),
+            open: VecDeque::new(),
+        }
+    }
+
+    /// Uses the given file paths, relative to the output directory, instead
+    /// of the default `<group>.m3u` for the listed keys. Keys that share a
+    /// path are written to the same file.
+    pub fn with_paths(mut self, paths: HashMap<GroupKey, PathBuf>) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    /// Sets how default file and directory names are sanitized.
+    pub fn with_strategy(mut self, strategy: FilenameStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// For nested keys, also writes an aggregate playlist for every
+    /// directory level, next to its directory: `UK.m3u` holds everything
+    /// under `UK/`.
+    pub fn with_aggregates(mut self, aggregates: bool) -> Self {
+        self.aggregates = aggregates;
+        self
+    }
+
+    /// Compresses every file written, adding the compression's extension to
+    /// its path.
+    pub fn with_compression(mut self, compression: OutputCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Writes `header` attributes, such as an EPG link in `url-tvg`, into the
+    /// `#EXTM3U` line of every file.
+    pub fn with_header(mut self, header: Attributes) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets how many files may be open at once, at least one. Defaults to
+    /// [`DEFAULT_MAX_OPEN_FILES`].
+    pub fn with_max_open_files(mut self, max_open: usize) -> Self {
+        self.max_open = max_open.max(1);
+        self
+    }
+
+    /// Appends `entry` to the files of all its `keys`, and to the aggregate
+    /// playlists of their directories if enabled. An entry is written at
+    /// most once to each file.
+    pub fn write_all(&mut self, keys: &[GroupKey], entry: &Entry) -> io::Result<()> {
+        let mut targets: Vec<(PathBuf, &GroupKey, bool)> = Vec::new();
+        let ancestors: Vec<GroupKey> = if self.aggregates {
+            keys.iter().flat_map(GroupKey::ancestors).collect()
+        } else {
+            Vec::new()
+        };
+        let all = ancestors
+            .iter()
+            .map(|key| (key, true))
+            .chain(keys.iter().map(|key| (key, false)));
+        for (key, aggregate) in all {
+            let path = self.path_for(key);
+            if !targets.iter().any(|(p, _, _)| *p == path) {
+                targets.push((path, key, aggregate));
+            }
+        }
+
+        for (path, key, aggregate) in targets {
+            self.write_to(path, key, entry, aggregate)?;
+        }
+        Ok(())
+    }
+
+    /// Appends `entry` to the file of `key`.
+    pub fn write(&mut self, key: &GroupKey, entry: &Entry) -> io::Result<()> {
+        let path = self.path_for(key);
+        self.write_to(path, key, entry, false)
+    }
+
+    fn path_for(&self, key: &GroupKey) -> PathBuf {
+        let path = match self.paths.get(key) {
+            Some(path) => self.output_dir.join(path),
+            None => self.output_dir.join(group_file_path(key, self.strategy)),
+        };
+        self.compression.file_path(&path)
+    }
+
+    fn write_to(
+        &mut self,
+        path: PathBuf,
+        key: &GroupKey,
+        entry: &Entry,
+        aggregate: bool,
+    ) -> io::Result<()> {
+        let file = match self.files.entry(path.clone()) {
+            hash_map::Entry::Occupied(occupied) => occupied.into_mut(),
+            hash_map::Entry::Vacant(vacant) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                self.order.push(path.clone());
+                vacant.insert(OutputFile {
+                    keys: Vec::new(),
+                    writer: None,
+                    count: 0,
+                    aggregate,
+                })
+            }
+        };
+        if !file.keys.contains(key) {
+            file.keys.push(key.clone());
+        }
+
+        if file.writer.is_some() {
+            if let Some(index) = self.open.iter().position(|open| *open == path) {
+                let recent = self.open.remove(index).expect("index is in range");
+                self.open.push_back(recent);
+            }
+        } else {
+            let created = file.count == 0;
+            if self.open.len() >= self.max_open {
+                self.close_oldest()?;
+            }
+            let handle = self.open_file(&path, created)?;
+            let mut writer = self.compression.writer(handle);
+            if created {
+                write_header_with_attributes(&mut writer, &self.header)?;
+            }
+            self.open.push_back(path.clone());
+            let file = self.files.get_mut(&path).expect("file was just recorded");
+            file.writer = Some(writer);
+        }
+
+        let file = self.files.get_mut(&path).expect("file was just recorded");
+        write_entry(file.writer.as_mut().expect("file was just opened"), entry)?;
+        file.count += 1;
+        Ok(())
+    }
+
+    /// Creates the file at `path`, or reopens it for appending. If the
+    /// process runs out of file descriptors first, closes open files until
+    /// it succeeds and keeps that lower cap from then on.
+    fn open_file(&mut self, path: &Path, create: bool) -> io::Result<fs::File> {
+        loop {
+            let opened = if create {
+                fs::File::create(path)
+            } else {
+                fs::OpenOptions::new().append(true).open(path)
+            };
+            match opened {
+                Err(error) if is_too_many_open_files(&error) && !self.open.is_empty() => {
+                    self.max_open = self.open.len();
+                    self.close_oldest()?;
+                }
+                opened => return opened,
+            }
+        }
+    }
+
+    fn close_oldest(&mut self) -> io::Result<()> {
+        if let Some(oldest) = self.open.pop_front() {
+            let file = self.files.get_mut(&oldest).expect("open files are known");
+            if let Some(writer) = file.writer.take() {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes every file, returning them in creation order.
+    pub fn finish(mut self) -> io::Result<Vec<WrittenGroup>> {
+        let mut written = Vec::with_capacity(self.order.len());
+        for path in self.order {
+            let file = self
+                .files
+                .remove(&path)
+                .expect("every created file is open");
+            if let Some(writer) = file.writer {
+                writer.finish()?;
+            }
+            written.push(WrittenGroup {
+                keys: file.keys,
+                path,
+                count: file.count,
+                aggregate: file.aggregate,
+            });
+        }
+        Ok(written)
+    }
+}
+
+// EMFILE, the per-process limit on open files, on Linux, macOS and the BSDs
+fn is_too_many_open_files(error: &io::Error) -> bool {
+    cfg!(unix) && error.raw_os_error() == Some(24)
+}
+
+/// Streams the playlist read from `reader` into per-group files in
+/// `output_dir`, grouping with `group_by`.
+pub fn split_reader<R: BufRead>(
+    reader: R,
+    output_dir: &Path,
+    group_by: &GroupBy,
+) -> io::Result<Vec<WrittenGroup>> {
+    let mut splitter = Splitter::new(output_dir);
+    for entry in entries(reader) {
+        let entry = entry?;
+        splitter.write_all(&group_by.keys(&entry), &entry)?;
+    }
+    splitter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_split_reader() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports",Sports 1
+http://example.com/sports1
+#EXTINF:-1 group-title="News",News 1
+#EXTVLCOPT:http-user-agent=VLC
+http://example.com/news1
+#EXTINF:-1 group-title="Sports",Sports 2
+http://example.com/sports2
+"#;
+
+        let mut written =
+            split_reader(content.as_bytes(), temp_dir.path(), &GroupBy::default()).unwrap();
+        written.sort_by(|a, b| a.keys.cmp(&b.keys));
+
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0].keys, vec![GroupKey::from("News")]);
+        assert_eq!(written[0].count, 1);
+        assert_eq!(written[1].path, temp_dir.path().join("Sports.m3u"));
+        assert_eq!(written[1].count, 2);
+
+        let news = fs::read_to_string(temp_dir.path().join("News.m3u")).unwrap();
+        assert_eq!(
+            news,
+            "#EXTM3U\n\
+             #EXTINF:-1 group-title=\"News\",News 1\n\
+             #EXTVLCOPT:http-user-agent=VLC\n\
+             http://example.com/news1\n"
+        );
+        let sports = fs::read_to_string(temp_dir.path().join("Sports.m3u")).unwrap();
+        assert_eq!(sports.matches("http://example.com/sports").count(), 2);
+    }
+
+    #[test]
+    fn test_splitter_nested_with_aggregates() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"#EXTM3U
+#EXTINF:-1 tvg-country="UK" group-title="Sports;HD",Sky Sports HD
+http://example.com/sky
+#EXTINF:-1 tvg-country="UK" group-title="News",BBC News
+http://example.com/bbc
+#EXTINF:-1 tvg-country="FR" group-title="News",France 24
+http://example.com/f24
+"#;
+        let group_by = GroupBy::new("tvg-country", "Unknown")
+            .then_by("group-title")
+            .with_delimiter(";");
+
+        let mut splitter = Splitter::new(temp_dir.path()).with_aggregates(true);
+        for entry in entries(content.as_bytes()) {
+            let entry = entry.unwrap();
+            splitter.write_all(&group_by.keys(&entry), &entry).unwrap();
+        }
+        let mut written = splitter.finish().unwrap();
+        written.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let summary: Vec<(PathBuf, usize, bool)> = written
+            .into_iter()
+            .map(|g| {
+                let path = g.path.strip_prefix(temp_dir.path()).unwrap().to_path_buf();
+                (path, g.count, g.aggregate)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (PathBuf::from("FR/News.m3u"), 1, false),
+                (PathBuf::from("FR.m3u"), 1, true),
+                (PathBuf::from("UK/HD.m3u"), 1, false),
+                (PathBuf::from("UK/News.m3u"), 1, false),
+                (PathBuf::from("UK/Sports.m3u"), 1, false),
+                (PathBuf::from("UK.m3u"), 2, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_splitter_with_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let entry = Entry::new(
+            r#"#EXTINF:-1 group-title="News",News 1"#.to_string(),
+            "http://example.com/news1".to_string(),
+        );
+        let paths = HashMap::from([(GroupKey::from("News"), PathBuf::from("01-News.m3u8"))]);
+
+        let mut header = Attributes::new();
+        header.insert("url-tvg", "http://example.com/epg.xml");
+
+        let mut splitter = Splitter::new(temp_dir.path())
+            .with_paths(paths)
+            .with_header(header);
+        splitter.write(&GroupKey::from("News"), &entry).unwrap();
+        splitter.write(&GroupKey::from("Sports"), &entry).unwrap();
+        splitter.finish().unwrap();
+
+        assert!(temp_dir.path().join("01-News.m3u8").exists());
+        assert!(!temp_dir.path().join("News.m3u").exists());
+        let sports = fs::read_to_string(temp_dir.path().join("Sports.m3u")).unwrap();
+        assert!(sports.starts_with("#EXTM3U url-tvg=\"http://example.com/epg.xml\"\n"));
+    }
+
+    #[test]
+    fn test_splitter_merges_keys_sharing_a_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let entry = Entry::new(
+            r#"#EXTINF:-1 group-title="Kids & Family",Kids 1"#.to_string(),
+            "http://example.com/kids1".to_string(),
+        );
+        let a = GroupKey::from("Kids & Family");
+        let b = GroupKey::from("Kids  Family");
+        let paths = HashMap::from([
+            (a.clone(), PathBuf::from("Kids__Family.m3u")),
+            (b.clone(), PathBuf::from("Kids__Family.m3u")),
+        ]);
+
+        let mut splitter = Splitter::new(temp_dir.path()).with_paths(paths);
+        splitter.write(&a, &entry).unwrap();
+        splitter.write(&b, &entry).unwrap();
+        // Written once even though the entry belongs to both groups
+        splitter.write_all(&[a.clone(), b.clone()], &entry).unwrap();
+        let written = splitter.finish().unwrap();
+
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].keys, vec![a, b]);
+        assert_eq!(written[0].count, 3);
+    }
+
+    #[test]
+    fn test_splitter_with_compression() {
+        use std::io::Read;
+
+        let temp_dir = TempDir::new().unwrap();
+        let entry = Entry::new(
+            r#"#EXTINF:-1 group-title="News",News 1"#.to_string(),
+            "http://example.com/news1".to_string(),
+        );
+
+        let mut splitter = Splitter::new(temp_dir.path()).with_compression(OutputCompression::Gzip);
+        splitter.write(&GroupKey::from("News"), &entry).unwrap();
+        let written = splitter.finish().unwrap();
+        assert_eq!(written[0].path, temp_dir.path().join("News.m3u.gz"));
+
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(fs::File::open(&written[0].path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            content,
+            "#EXTM3U\n#EXTINF:-1 group-title=\"News\",News 1\nhttp://example.com/news1\n"
+        );
+    }
+
+    #[test]
+    fn test_splitter_reopens_closed_files() {
+        use std::io::Read;
+
+        for compression in OutputCompression::ALL {
+            let temp_dir = TempDir::new().unwrap();
+            let mut splitter = Splitter::new(temp_dir.path())
+                .with_compression(compression)
+                .with_max_open_files(2);
+            for i in 0..3 {
+                for group in ["A", "B", "C"] {
+                    let entry = Entry::new(
+                        format!("#EXTINF:-1 group-title=\"{}\",{} {}", group, group, i),
+                        format!("http://example.com/{}{}", group, i),
+                    );
+                    splitter.write(&GroupKey::from(group), &entry).unwrap();
+                }
+                assert!(splitter.open.len() <= 2);
+            }
+            let written = splitter.finish().unwrap();
+            assert_eq!(written.len(), 3);
+
+            for group in written {
+                assert_eq!(group.count, 3);
+                let file = io::BufReader::new(fs::File::open(&group.path).unwrap());
+                let mut content = String::new();
+                crate::compress::decompress(file)
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap();
+                assert_eq!(content.matches("#EXTM3U").count(), 1);
+                assert_eq!(content.matches("#EXTINF").count(), 3);
+            }
+        }
+    }
+}
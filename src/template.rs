@@ -0,0 +1,323 @@
+//! Output file name templates such as `{index:03}-{group}-{count}.m3u8`.
+
+use crate::filename::FilenameStrategy;
+use crate::group::GroupKey;
+use crate::writer::group_dir_path;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Placeholders available in every template, besides the grouping
+/// attributes.
+pub const PLACEHOLDERS: &[&str] = &["group", "count", "index", "stem"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    /// The group's own name.
+    Group,
+    /// Number of channels in the group.
+    Count,
+    /// 1-based position of the group in order of first appearance.
+    Index,
+    /// File stem of the input playlist.
+    Stem,
+    /// Value of a grouping attribute, by level.
+    Level(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field {
+        field: Field,
+        width: usize,
+        zero_pad: bool,
+    },
+}
+
+/// Error returned when a template cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError(String);
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid name template: {}", self.0)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Values a template is rendered with for one group.
+#[derive(Debug, Clone, Copy)]
+pub struct NameContext<'a> {
+    pub key: &'a GroupKey,
+    pub count: usize,
+    pub index: usize,
+    pub stem: &'a str,
+}
+
+/// A parsed file name template.
+///
+/// Placeholders are written `{name}` or `{name:WIDTH}`, where a width
+/// starting with `0` zero-pads numbers (`{index:03}` gives `007`). Besides
+/// [`PLACEHOLDERS`], the name of any grouping attribute is a placeholder for
+/// that level's value, e.g. `{tvg-country}`. `{{` and `}}` are literal
+/// braces. Text values are sanitized with the template's
+/// [`FilenameStrategy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+    strategy: FilenameStrategy,
+}
+
+impl NameTemplate {
+    /// Parses `template`, accepting the grouping `attributes` as extra
+    /// placeholders. Unknown placeholders, malformed widths and templates
+    /// that would give every group the same name are rejected.
+    pub fn parse(template: &str, attributes: &[String]) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => {
+                                return Err(TemplateError(format!(
+                                    "unclosed placeholder {{{}",
+                                    spec
+                                )))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&spec, attributes)?);
+                }
+                '}' => return Err(TemplateError("unmatched }".to_string())),
+                '/' | '\\' => {
+                    return Err(TemplateError(
+                        "path separators are not allowed in a file name".to_string(),
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        let distinguishes_groups = parts.iter().any(|part| {
+            matches!(
+                part,
+                Part::Field {
+                    field: Field::Group | Field::Index | Field::Level(_),
+                    ..
+                }
+            )
+        });
+        if !distinguishes_groups {
+            return Err(TemplateError(format!(
+                "{:?} gives every group the same name; use {{group}} or {{index}}",
+                template
+            )));
+        }
+
+        Ok(NameTemplate {
+            parts,
+            strategy: FilenameStrategy::default(),
+        })
+    }
+
+    /// Sets how text values and directory names are sanitized.
+    pub fn with_strategy(mut self, strategy: FilenameStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Renders the file name for one group.
+    pub fn render(&self, context: &NameContext<'_>) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push_str(text),
+                Part::Field {
+                    field,
+                    width,
+                    zero_pad,
+                } => {
+                    let value = match field {
+                        Field::Group => self.strategy.file_stem(context.key.name()),
+                        Field::Count => context.count.to_string(),
+                        Field::Index => context.index.to_string(),
+                        Field::Stem => self.strategy.sanitize(context.stem),
+                        Field::Level(level) => context
+                            .key
+                            .components()
+                            .get(*level)
+                            .map(|value| self.strategy.file_stem(value))
+                            .unwrap_or_default(),
+                    };
+                    let pad = if *zero_pad { '0' } else { ' ' };
+                    for _ in value.chars().count()..*width {
+                        name.push(pad);
+                    }
+                    name.push_str(&value);
+                }
+            }
+        }
+        name
+    }
+
+    /// Renders the path of a group's file relative to the output directory,
+    /// keeping one directory per enclosing level of a nested key.
+    pub fn render_path(&self, context: &NameContext<'_>) -> PathBuf {
+        group_dir_path(context.key, self.strategy).join(self.render(context))
+    }
+}
+
+fn parse_placeholder(spec: &str, attributes: &[String]) -> Result<Part, TemplateError> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (spec, None),
+    };
+
+    let field = match name {
+        "group" => Field::Group,
+        "count" => Field::Count,
+        "index" => Field::Index,
+        "stem" => Field::Stem,
+        _ => match attributes.iter().position(|a| a.eq_ignore_ascii_case(name)) {
+            Some(level) => Field::Level(level),
+            None => {
+                let mut available: Vec<&str> = PLACEHOLDERS.to_vec();
+                available.extend(attributes.iter().map(String::as_str));
+                return Err(TemplateError(format!(
+                    "unknown placeholder {{{}}}; available: {}",
+                    name,
+                    available.join(", ")
+                )));
+            }
+        },
+    };
+
+    let (width, zero_pad) = match format {
+        None => (0, false),
+        Some(format) => {
+            let width = format.parse::<usize>().map_err(|_| {
+                TemplateError(format!("invalid width {:?} in {{{}}}", format, spec))
+            })?;
+            if !matches!(field, Field::Count | Field::Index) && format.starts_with('0') {
+                return Err(TemplateError(format!(
+                    "zero padding only applies to numbers, not {{{}}}",
+                    name
+                )));
+            }
+            (width, format.starts_with('0'))
+        }
+    };
+
+    Ok(Part::Field {
+        field,
+        width,
+        zero_pad,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template = NameTemplate::parse(
+            "{index:03}-{group}-{count}.m3u8",
+            &attributes(&["group-title"]),
+        )
+        .unwrap();
+        let key = GroupKey::from("Kids & Family");
+        let name = template.render(&NameContext {
+            key: &key,
+            count: 12,
+            index: 7,
+            stem: "playlist",
+        });
+        assert_eq!(name, "007-Kids__Family-12.m3u8");
+    }
+
+    #[test]
+    fn test_render_path_nested_with_attribute_placeholders() {
+        let template = NameTemplate::parse(
+            "{stem}_{tvg-country}_{group-title}.m3u",
+            &attributes(&["tvg-country", "group-title"]),
+        )
+        .unwrap();
+        let key = GroupKey::new(vec!["UK".to_string(), "Sports".to_string()]);
+        let path = template.render_path(&NameContext {
+            key: &key,
+            count: 3,
+            index: 1,
+            stem: "provider a",
+        });
+        assert_eq!(path, PathBuf::from("UK").join("provider_a_UK_Sports.m3u"));
+    }
+
+    #[test]
+    fn test_render_with_strategy() {
+        let template = NameTemplate::parse("{index}-{group}.m3u", &[])
+            .unwrap()
+            .with_strategy(FilenameStrategy::Slug);
+        let key = GroupKey::from("Kids & Family");
+        let name = template.render(&NameContext {
+            key: &key,
+            count: 1,
+            index: 2,
+            stem: "",
+        });
+        assert_eq!(name, "2-kids-family.m3u");
+    }
+
+    #[test]
+    fn test_literal_braces() {
+        let template = NameTemplate::parse("{{{group}}}.m3u", &[]).unwrap();
+        let key = GroupKey::from("News");
+        let name = template.render(&NameContext {
+            key: &key,
+            count: 1,
+            index: 1,
+            stem: "",
+        });
+        assert_eq!(name, "{News}.m3u");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let attrs = attributes(&["group-title"]);
+        let err = NameTemplate::parse("{grop}.m3u", &attrs).unwrap_err();
+        assert!(err.to_string().contains("unknown placeholder {grop}"));
+        assert!(err.to_string().contains("group-title"));
+        assert!(NameTemplate::parse("{group.m3u", &attrs).is_err());
+        assert!(NameTemplate::parse("group}.m3u", &attrs).is_err());
+        assert!(NameTemplate::parse("{index:x}.m3u", &attrs).is_err());
+        assert!(NameTemplate::parse("{group:03}.m3u", &attrs).is_err());
+        assert!(NameTemplate::parse("out/{group}.m3u", &attrs).is_err());
+        assert!(NameTemplate::parse("{stem}-{count}.m3u", &attrs).is_err());
+    }
+}
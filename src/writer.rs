@@ -0,0 +1,263 @@
+//! M3U serializer.
+
+use crate::extinf::{format_header, Attributes};
+use crate::filename::FilenameStrategy;
+use crate::group::GroupKey;
+use crate::playlist::{Entry, Playlist};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes the `#EXTM3U` header line.
+pub fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "#EXTM3U")
+}
+
+/// Writes the `#EXTM3U` header line with playlist attributes such as
+/// `url-tvg`.
+pub fn write_header_with_attributes<W: Write>(
+    writer: &mut W,
+    attributes: &Attributes,
+) -> io::Result<()> {
+    writeln!(writer, "{}", format_header(attributes))
+}
+
+/// Writes one entry: its comments, `#EXTINF` line, directives and URL. A
+/// plain entry is written without `#EXTINF` line.
+///
+/// An entry from lossless parsing is written exactly as it was read, unless
+/// its comments, directives or URL were changed. A changed `#EXTINF` line,
+/// e.g. by [`Entry::set_attribute`], is written in place of the original.
+pub fn write_entry<W: Write>(writer: &mut W, entry: &Entry) -> io::Result<()> {
+    if let Some(raw) = entry.raw.as_ref().filter(|raw| raw.matches(entry)) {
+        for (i, line) in raw.lines.iter().enumerate() {
+            let content = line.trim_start_matches('\u{feff}').trim();
+            if Some(i) == raw.extinf && content != entry.extinf_line {
+                let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+                write!(writer, "{}{}", entry.extinf_line, ending)?;
+            } else {
+                writer.write_all(line.as_bytes())?;
+            }
+        }
+        return Ok(());
+    }
+
+    for comment in &entry.comments {
+        writeln!(writer, "{}", comment)?;
+    }
+    if !entry.is_plain() {
+        writeln!(writer, "{}", entry.extinf_line)?;
+    }
+    for directive in &entry.directives {
+        writeln!(writer, "{}", directive)?;
+    }
+    writeln!(writer, "{}", entry.url)
+}
+
+/// Writes `entries` as an extended M3U playlist, header included.
+pub fn write_playlist<W: Write>(writer: &mut W, entries: &[Entry]) -> io::Result<()> {
+    write_header(writer)?;
+    for entry in entries {
+        write_entry(writer, entry)?;
+    }
+    Ok(())
+}
+
+/// Writes a playlist from [`parse_lossless`](crate::parse_lossless) back
+/// exactly as it was read: its preamble, entries and trailer. The preamble
+/// holds the `#EXTM3U` line, so no header is written for a playlist parsed
+/// otherwise; use [`write_playlist`] for those.
+pub fn write_lossless<W: Write>(writer: &mut W, playlist: &Playlist) -> io::Result<()> {
+    for line in &playlist.preamble {
+        writer.write_all(line.as_bytes())?;
+    }
+    for entry in &playlist.entries {
+        write_entry(writer, entry)?;
+    }
+    for line in &playlist.trailer {
+        writer.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Returns the directory holding the file for `key`, relative to the
+/// output directory: empty for a flat key, `UK` for `UK / Sports`.
+pub fn group_dir_path(key: &GroupKey, strategy: FilenameStrategy) -> PathBuf {
+    key.components()[..key.depth() - 1]
+        .iter()
+        .map(|component| strategy.file_stem(component))
+        .collect()
+}
+
+/// Returns the path of the file for `key`, relative to the output
+/// directory: `Sports.m3u`, or `UK/Sports.m3u` for a nested key.
+pub fn group_file_path(key: &GroupKey, strategy: FilenameStrategy) -> PathBuf {
+    group_dir_path(key, strategy).join(format!("{}.m3u", strategy.file_stem(key.name())))
+}
+
+/// Writes `entries` to the file for `key` under `output_dir`, creating
+/// directories for nested keys, and returns the path of the written file.
+/// File names use the default [`FilenameStrategy`].
+pub fn write_group_file(
+    output_dir: &Path,
+    key: &GroupKey,
+    entries: &[Entry],
+) -> io::Result<PathBuf> {
+    let filepath = output_dir.join(group_file_path(key, FilenameStrategy::default()));
+    if let Some(parent) = filepath.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = BufWriter::new(fs::File::create(&filepath)?);
+    write_playlist(&mut file, entries)?;
+    file.flush()?;
+
+    Ok(filepath)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_group_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let channels = vec![
+            Entry::new(
+                r#"#EXTINF:-1 group-title="Sports" tvg-id="channel1",Sports Channel"#.to_string(),
+                "http://example.com/sports.m3u8".to_string(),
+            ),
+            Entry::new(
+                r#"#EXTINF:-1 group-title="Sports" tvg-id="channel2",Sports Channel 2"#.to_string(),
+                "http://example.com/sports2.m3u8".to_string(),
+            ),
+        ];
+
+        write_group_file(output_dir, &GroupKey::from("Sports"), &channels).unwrap();
+
+        let output_file = output_dir.join("Sports.m3u");
+        assert!(output_file.exists());
+
+        let content = fs::read_to_string(&output_file).unwrap();
+        assert!(content.starts_with("#EXTM3U\n"));
+        assert!(content.contains("http://example.com/sports.m3u8"));
+        assert!(content.contains("http://example.com/sports2.m3u8"));
+    }
+
+    #[test]
+    fn test_write_group_file_sanitized_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let channels = vec![Entry::new(
+            r#"#EXTINF:-1 group-title="Kids & Family" tvg-id="channel1",Kids Channel"#.to_string(),
+            "http://example.com/kids.m3u8".to_string(),
+        )];
+
+        write_group_file(output_dir, &GroupKey::from("Kids & Family"), &channels).unwrap();
+
+        let output_file = output_dir.join("Kids__Family.m3u");
+        assert!(output_file.exists());
+    }
+
+    #[test]
+    fn test_write_group_file_directives_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let mut entry = Entry::new(
+            r#"#EXTINF:-1 group-title="Movies",Movie Channel"#.to_string(),
+            "http://example.com/movie.m3u8".to_string(),
+        );
+        entry.directives = vec![
+            "#EXTVLCOPT:http-referrer=http://example.com/".to_string(),
+            "#EXTHTTP:{\"User-Agent\":\"VLC\"}".to_string(),
+        ];
+
+        write_group_file(output_dir, &GroupKey::from("Movies"), &[entry]).unwrap();
+
+        let content = fs::read_to_string(output_dir.join("Movies.m3u")).unwrap();
+        assert_eq!(
+            content,
+            "#EXTM3U\n\
+             #EXTINF:-1 group-title=\"Movies\",Movie Channel\n\
+             #EXTVLCOPT:http-referrer=http://example.com/\n\
+             #EXTHTTP:{\"User-Agent\":\"VLC\"}\n\
+             http://example.com/movie.m3u8\n"
+        );
+    }
+
+    #[test]
+    fn test_write_playlist_plain_entries() {
+        let mut buffer = Vec::new();
+        let entries = [
+            Entry::plain("Music/song.mp3".to_string()),
+            Entry::new(
+                "#EXTINF:-1,Radio".to_string(),
+                "http://example.com/radio".to_string(),
+            ),
+        ];
+        write_playlist(&mut buffer, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "#EXTM3U\n\
+             Music/song.mp3\n\
+             #EXTINF:-1,Radio\n\
+             http://example.com/radio\n"
+        );
+    }
+
+    #[test]
+    fn test_write_group_file_non_ascii_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let channels = vec![Entry::new(
+            r#"#EXTINF:-1 group-title="Café" tvg-id="channel1",Cafe Channel"#.to_string(),
+            "http://example.com/cafe.m3u8".to_string(),
+        )];
+
+        write_group_file(output_dir, &GroupKey::from("Café"), &channels).unwrap();
+
+        let output_file = output_dir.join("Caf.m3u");
+        assert!(output_file.exists());
+    }
+
+    #[test]
+    fn test_write_group_file_nested_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let entries = vec![Entry::new(
+            r#"#EXTINF:-1 tvg-country="UK" group-title="Kids & Family",Kids Channel"#.to_string(),
+            "http://example.com/kids.m3u8".to_string(),
+        )];
+        let key = GroupKey::new(vec!["UK".to_string(), "Kids & Family".to_string()]);
+
+        let path = write_group_file(output_dir, &key, &entries).unwrap();
+
+        assert_eq!(path, output_dir.join("UK").join("Kids__Family.m3u"));
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_group_file_path_strategies() {
+        let key = GroupKey::new(vec!["Россия".to_string(), "北京".to_string()]);
+        assert_eq!(
+            group_file_path(&key, FilenameStrategy::Unicode),
+            PathBuf::from("Россия").join("北京.m3u")
+        );
+        assert_eq!(
+            group_file_path(&key, FilenameStrategy::Transliterate),
+            PathBuf::from("Rossiia").join("Bei_Jing.m3u")
+        );
+
+        let ascii = group_file_path(&key, FilenameStrategy::Ascii);
+        let file_name = ascii.file_name().unwrap().to_str().unwrap();
+        assert!(file_name.starts_with("group-"), "{}", file_name);
+        assert!(file_name.ends_with(".m3u"));
+    }
+}
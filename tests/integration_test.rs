@@ -288,3 +288,1625 @@ fn test_empty_m3u_file() {
         "Should warn about empty file"
     );
 }
+
+#[test]
+fn test_directive_lines_preserved() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create test M3U file with directive lines between EXTINF and URL
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="Movies" tvg-id="movie1",Movie Channel 1
+#EXTVLCOPT:http-user-agent=Mozilla/5.0
+#KODIPROP:inputstream=inputstream.adaptive
+http://example.com/movie1.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    // Run the binary
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+
+    let movies_content = fs::read_to_string(output_dir.join("Movies.m3u")).unwrap();
+    let lines: Vec<&str> = movies_content.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "#EXTM3U",
+            r#"#EXTINF:-1 group-title="Movies" tvg-id="movie1",Movie Channel 1"#,
+            "#EXTVLCOPT:http-user-agent=Mozilla/5.0",
+            "#KODIPROP:inputstream=inputstream.adaptive",
+            "http://example.com/movie1.m3u8",
+        ]
+    );
+}
+
+#[test]
+fn test_split_by_attribute_with_fallback() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create test M3U file with tvg-country attributes
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 tvg-country="UK" group-title="News",BBC News
+http://example.com/bbc.m3u8
+#EXTINF:-1 tvg-country="FR" group-title="News",France 24
+http://example.com/f24.m3u8
+#EXTINF:-1 tvg-country="UK" group-title="Sports",Sky Sports
+http://example.com/sky.m3u8
+#EXTINF:-1 group-title="Sports",No Country
+http://example.com/nocountry.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    // Run the binary with --by and --fallback
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--by")
+        .arg("tvg-country")
+        .arg("--fallback")
+        .arg("International")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+
+    let uk_content = fs::read_to_string(output_dir.join("UK.m3u")).unwrap();
+    assert!(uk_content.contains("http://example.com/bbc.m3u8"));
+    assert!(uk_content.contains("http://example.com/sky.m3u8"));
+    assert!(output_dir.join("FR.m3u").exists(), "FR.m3u should exist");
+    assert!(
+        output_dir.join("International.m3u").exists(),
+        "International.m3u should exist for channels without tvg-country"
+    );
+    assert!(!output_dir.join("Unknown.m3u").exists());
+}
+
+#[test]
+fn test_split_multi_valued_groups() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create test M3U file with semicolon-separated groups
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports;HD" tvg-id="sports1",Sports HD
+http://example.com/sports1.m3u8
+#EXTINF:-1 group-title="News" tvg-id="news1",News Channel 1
+http://example.com/news1.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    // Run the binary with --split-values using the default delimiter
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--split-values")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+
+    for name in ["Sports.m3u", "HD.m3u"] {
+        let content = fs::read_to_string(output_dir.join(name)).unwrap();
+        assert!(content.contains("http://example.com/sports1.m3u8"));
+    }
+    assert!(output_dir.join("News.m3u").exists());
+    assert!(!output_dir.join("SportsHD.m3u").exists());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("1 of 2 channels are in more than one group"),
+        "stdout: {:?}",
+        stdout
+    );
+}
+
+#[test]
+fn test_nested_split_with_aggregates() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create test M3U file with country and group attributes
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 tvg-country="UK" group-title="News",BBC News
+http://example.com/bbc.m3u8
+#EXTINF:-1 tvg-country="UK" group-title="Sports",Sky Sports
+http://example.com/sky.m3u8
+#EXTINF:-1 tvg-country="FR" group-title="News",France 24
+http://example.com/f24.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    // Run the binary with a composite --by and --aggregate
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--by")
+        .arg("tvg-country,group-title")
+        .arg("--aggregate")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert!(output_dir.join("UK").join("News.m3u").exists());
+    assert!(output_dir.join("UK").join("Sports.m3u").exists());
+    assert!(output_dir.join("FR").join("News.m3u").exists());
+
+    // Aggregate playlists sit next to their directory
+    let uk_content = fs::read_to_string(output_dir.join("UK.m3u")).unwrap();
+    assert!(uk_content.contains("http://example.com/bbc.m3u8"));
+    assert!(uk_content.contains("http://example.com/sky.m3u8"));
+    assert!(!uk_content.contains("http://example.com/f24.m3u8"));
+    assert!(output_dir.join("FR.m3u").exists());
+}
+
+#[test]
+fn test_name_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("provider.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="News" tvg-id="news1",News Channel 1
+http://example.com/news1.m3u8
+#EXTINF:-1 group-title="Sports" tvg-id="sports1",Sports Channel 1
+http://example.com/sports1.m3u8
+#EXTINF:-1 group-title="Sports" tvg-id="sports2",Sports Channel 2
+http://example.com/sports2.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    // Run the binary with a name template
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--name-template")
+        .arg("{stem}-{index:03}-{group}-{count}.m3u8")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert!(output_dir.join("provider-001-News-1.m3u8").exists());
+    assert!(output_dir.join("provider-002-Sports-2.m3u8").exists());
+    assert!(!output_dir.join("News.m3u").exists());
+
+    // {index} counts in order of first appearance, whatever the --sort
+    // order and the groups filtered out
+    let sorted_dir = temp_dir.path().join("sorted");
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&sorted_dir)
+        .arg("--name-template")
+        .arg("{index}-{group}.m3u")
+        .arg("--sort")
+        .arg("count")
+        .arg("--desc")
+        .arg("--exclude-group")
+        .arg("News")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    assert!(sorted_dir.join("2-Sports.m3u").exists());
+    assert!(!sorted_dir.join("1-Sports.m3u").exists());
+}
+
+#[test]
+fn test_invalid_name_template_fails_early() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    fs::write(
+        &input_file,
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",News\nhttp://example.com/news.m3u8\n",
+    )
+    .unwrap();
+
+    // Run the binary with an unknown placeholder
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--name-template")
+        .arg("{grop}.m3u")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unknown placeholder {grop}"),
+        "stderr: {:?}",
+        stderr
+    );
+    assert!(!output_dir.exists(), "Nothing should be written");
+}
+
+#[test]
+fn test_filename_strategies() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+
+    // Create test M3U file with non-ASCII group names
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="Música" tvg-id="music1",Music Channel
+http://example.com/music1.m3u8
+#EXTINF:-1 group-title="北京" tvg-id="beijing1",Beijing Channel
+http://example.com/beijing1.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    let binary = get_binary_path();
+    let run = |strategy: &str| {
+        let output_dir = temp_dir.path().join(strategy);
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .arg("--filenames")
+            .arg(strategy)
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "Command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output_dir
+    };
+
+    let unicode_dir = run("unicode");
+    assert!(unicode_dir.join("Música.m3u").exists());
+    assert!(unicode_dir.join("北京.m3u").exists());
+
+    let transliterate_dir = run("transliterate");
+    assert!(transliterate_dir.join("Musica.m3u").exists());
+    assert!(transliterate_dir.join("Bei_Jing.m3u").exists());
+
+    // The default ASCII strategy falls back to a hashed name instead of ".m3u"
+    let ascii_dir = run("ascii");
+    assert!(ascii_dir.join("Msica.m3u").exists());
+    assert!(!ascii_dir.join(".m3u").exists());
+    let hashed = fs::read_dir(&ascii_dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("group-"))
+        .count();
+    assert_eq!(hashed, 1);
+}
+
+#[test]
+fn test_filename_collisions() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+
+    // Create test M3U file with two groups that sanitize to the same name
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="Kids & Family" tvg-id="kids1",Kids Channel 1
+http://example.com/kids1.m3u8
+#EXTINF:-1 group-title="Kids  Family" tvg-id="kids2",Kids Channel 2
+http://example.com/kids2.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    let binary = get_binary_path();
+    let run = |policy: &str| {
+        let output_dir = temp_dir.path().join(policy);
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .arg("--on-collision")
+            .arg(policy)
+            .output()
+            .expect("Failed to execute command");
+        (output_dir, output)
+    };
+
+    // Suffix (default): both groups are kept in separate files
+    let (suffix_dir, output) = run("suffix");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("collision"), "stdout: {:?}", stdout);
+    let first = fs::read_to_string(suffix_dir.join("Kids__Family.m3u")).unwrap();
+    let second = fs::read_to_string(suffix_dir.join("Kids__Family-2.m3u")).unwrap();
+    assert!(first.contains("http://example.com/kids1.m3u8"));
+    assert!(second.contains("http://example.com/kids2.m3u8"));
+
+    // Merge: both groups end up in one file
+    let (merge_dir, output) = run("merge");
+    assert!(output.status.success());
+    let merged = fs::read_to_string(merge_dir.join("Kids__Family.m3u")).unwrap();
+    assert!(merged.contains("http://example.com/kids1.m3u8"));
+    assert!(merged.contains("http://example.com/kids2.m3u8"));
+    assert!(!merge_dir.join("Kids__Family-2.m3u").exists());
+
+    // Error: nothing is written
+    let (error_dir, output) = run("error");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Kids__Family.m3u <- Kids & Family, Kids  Family"),
+        "stderr: {:?}",
+        stderr
+    );
+    assert!(!error_dir.exists(), "Nothing should be written");
+}
+
+#[test]
+fn test_sorted_group_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+
+    // Create test M3U file with groups of different sizes
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports",Sports 1
+http://example.com/sports1.m3u8
+#EXTINF:-1 group-title="News",News 1
+http://example.com/news1.m3u8
+#EXTINF:-1 group-title="Kids",Kids 1
+http://example.com/kids1.m3u8
+#EXTINF:-1 group-title="News",News 2
+http://example.com/news2.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    let binary = get_binary_path();
+    let listed_groups = |args: &[&str]| {
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(temp_dir.path().join("output"))
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let position = |needle: &str| stdout.find(needle).expect(needle);
+        let mut groups = vec!["Sports", "News", "Kids"];
+        groups.sort_by_key(|group| position(&format!("  {}: ", group)));
+        let mut created = vec!["Sports", "News", "Kids"];
+        created.sort_by_key(|group| position(&format!("Created: {}.m3u", group)));
+        assert_eq!(groups, created, "Statistics and writes use the same order");
+        groups
+    };
+
+    assert_eq!(listed_groups(&[]), ["Sports", "News", "Kids"]);
+    assert_eq!(
+        listed_groups(&["--sort", "name"]),
+        ["Kids", "News", "Sports"]
+    );
+    assert_eq!(
+        listed_groups(&["--sort", "count", "--desc"]),
+        ["News", "Sports", "Kids"]
+    );
+}
+
+#[test]
+fn test_include_exclude_group_filters() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create test M3U file
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="UK Sports",Sky Sports
+http://example.com/sky.m3u8
+#EXTINF:-1 group-title="UK News",BBC News
+http://example.com/bbc.m3u8
+#EXTINF:-1 group-title="uk adult",Adult
+http://example.com/adult.m3u8
+#EXTINF:-1 group-title="Music",Music
+http://example.com/music.m3u8
+#EXTINF:-1 group-title="Kids",Kids
+http://example.com/kids.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--include-group")
+        .arg("uk*")
+        .arg("--include-group")
+        .arg("re:kid.*")
+        .arg("--exclude-group")
+        .arg("UK Adult")
+        .arg("--ignore-case")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert!(output_dir.join("UK_Sports.m3u").exists());
+    assert!(output_dir.join("UK_News.m3u").exists());
+    assert!(output_dir.join("Kids.m3u").exists());
+    assert!(!output_dir.join("uk_adult.m3u").exists());
+    assert!(!output_dir.join("Music.m3u").exists());
+
+    // Filtered-out groups are reported rather than silently dropped
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Found 5 groups (2 filtered out)"));
+    assert!(stdout.contains("Filtered out 2 groups:"));
+    assert!(stdout.contains("  uk adult: 1 channels"));
+    assert!(stdout.contains("  Music: 1 channels"));
+}
+
+#[test]
+fn test_where_conditions() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create test M3U file
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 tvg-id="sky.uk" group-title="Sports",Sky Sports
+http://good.example.com/sky.m3u8
+#EXTINF:-1 tvg-id="sky4k.uk" group-title="Sports",Sky Sports 4K
+http://good.example.com/sky4k.m3u8
+#EXTINF:-1 tvg-id="bbc.uk" group-title="News",BBC News
+http://bad.example.com/bbc.m3u8
+#EXTINF:-1 group-title="News",No Id News
+http://good.example.com/noid.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    let binary = get_binary_path();
+    let run = |extra: &[&str]| {
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .arg("--where")
+            .arg(r"title!~(?i)\b(4K|UHD)\b")
+            .arg("--where")
+            .arg(r"url!~^https?://bad\.example\.com/")
+            .arg("--where")
+            .arg("tvg-id")
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "Command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    // Conditions apply to the statistics of a dry run as well
+    let stdout = run(&["--dry-run"]);
+    assert!(stdout.contains("Found 1 groups:"));
+    assert!(stdout.contains("Sports: 1 channels"));
+    assert!(stdout.contains("Dropped 3 channels by --where"));
+    assert!(!output_dir.exists());
+
+    run(&[]);
+    let sports = fs::read_to_string(output_dir.join("Sports.m3u")).unwrap();
+    assert!(sports.contains("http://good.example.com/sky.m3u8"));
+    assert!(!sports.contains("sky4k"));
+    assert!(!output_dir.join("News.m3u").exists());
+}
+
+#[test]
+fn test_invalid_where_condition_fails_early() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    fs::write(
+        &input_file,
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",News\nhttp://example.com/news.m3u8\n",
+    )
+    .unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(temp_dir.path().join("output"))
+        .arg("--where")
+        .arg("title~(open")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid condition"), "stderr: {:?}", stderr);
+}
+
+#[test]
+fn test_rename_groups_with_mapping_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let toml_file = temp_dir.path().join("groups.toml");
+    let csv_file = temp_dir.path().join("groups.csv");
+
+    // Create test M3U file with one group spelled three ways
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 tvg-id="sky1" group-title="UK | Sports",Sky Sports 1
+http://example.com/sky1.m3u8
+#EXTINF:-1 tvg-id="sky2" group-title="UK: SPORTS",Sky Sports 2
+http://example.com/sky2.m3u8
+#EXTINF:-1 tvg-id="sky3" group-title="Sports UK",Sky Sports 3
+http://example.com/sky3.m3u8
+#EXTINF:-1 tvg-id="bbc" group-title="News",BBC News
+http://example.com/bbc.m3u8
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+    fs::write(
+        &toml_file,
+        r#"
+[[rename]]
+from = "UK | Sports"
+to = "Sports-UK"
+
+[[rename]]
+from = 're:(?i)^(uk:\s*sports|sports uk)$'
+to = "Sports-UK"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        &csv_file,
+        "from,to\nUK | Sports,Sports-UK\nre:(?i)^(uk:\\s*sports|sports uk)$,Sports-UK\n",
+    )
+    .unwrap();
+
+    let binary = get_binary_path();
+    let run = |mapping: &Path, output_dir: &str, extra: &[&str]| {
+        let output_dir = temp_dir.path().join(output_dir);
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .arg("--rename")
+            .arg(mapping)
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "Command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output_dir
+    };
+
+    for (mapping, name) in [(&toml_file, "toml"), (&csv_file, "csv")] {
+        let output_dir = run(mapping, name, &[]);
+        let sports = fs::read_to_string(output_dir.join("Sports-UK.m3u")).unwrap();
+        assert_eq!(sports.matches("http://example.com/sky").count(), 3);
+        // The EXTINF lines are left as they were by default
+        assert!(sports.contains(r#"group-title="UK: SPORTS""#));
+        // Every spelling ends up in one file, whatever its case
+        let mut files: Vec<String> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["News.m3u", "Sports-UK.m3u"]);
+    }
+
+    let output_dir = run(&toml_file, "rewritten", &["--rewrite-group-title"]);
+    let sports = fs::read_to_string(output_dir.join("Sports-UK.m3u")).unwrap();
+    assert_eq!(sports.matches(r#"group-title="Sports-UK""#).count(), 3);
+    assert!(sports.contains(r#"#EXTINF:-1 tvg-id="sky2" group-title="Sports-UK",Sky Sports 2"#));
+}
+
+#[test]
+fn test_dedupe_channels() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+
+    // Create test M3U file with the same stream listed three times
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="UK",BBC One
+http://example.com/bbc1?token=a
+#EXTINF:-1 tvg-id="bbc1.uk" tvg-logo="bbc.png" group-title="News",BBC One HD
+http://example.com/bbc1?token=b
+#EXTINF:-1 tvg-id="itv.uk" group-title="UK",ITV
+http://example.com/itv
+#EXTINF:-1 group-title="UK",BBC One
+http://example.com/bbc1?token=c
+"#;
+
+    fs::write(&input_file, m3u_content).unwrap();
+
+    let binary = get_binary_path();
+    let run = |output_dir: &str, extra: &[&str]| {
+        let output_dir = temp_dir.path().join(output_dir);
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .arg("--dedupe")
+            .arg("normalized-url")
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "Command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        (output_dir, stdout)
+    };
+
+    let (first_dir, stdout) = run("first", &[]);
+    assert!(stdout.contains("Removed 2 duplicate channels (by normalized-url, keeping first)"));
+    assert!(stdout.contains(r#"removed "BBC One HD" (News) http://example.com/bbc1?token=b"#));
+    let uk = fs::read_to_string(first_dir.join("UK.m3u")).unwrap();
+    assert!(uk.contains("token=a"));
+    assert!(!uk.contains("token=c"));
+    assert!(!first_dir.join("News.m3u").exists());
+
+    let (best_dir, stdout) = run("best", &["--keep", "best"]);
+    assert!(stdout.contains(r#"kept "BBC One HD" (News)"#));
+    let news = fs::read_to_string(best_dir.join("News.m3u")).unwrap();
+    assert!(news.contains("token=b"));
+    let uk = fs::read_to_string(best_dir.join("UK.m3u")).unwrap();
+    assert!(!uk.contains("bbc1"));
+    assert!(uk.contains("http://example.com/itv"));
+
+    // The first copy is in an excluded group, so the next one is kept
+    let (excluded_dir, stdout) = run("excluded", &["--exclude-group", "UK"]);
+    assert!(stdout.contains("Removed 0 duplicate channels"));
+    assert!(!excluded_dir.join("UK.m3u").exists());
+    let news = fs::read_to_string(excluded_dir.join("News.m3u")).unwrap();
+    assert!(news.contains("token=b"));
+}
+
+#[test]
+fn test_multiple_inputs_merge_groups() {
+    let temp_dir = TempDir::new().unwrap();
+    let providers = temp_dir.path().join("providers");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir(&providers).unwrap();
+
+    // Create three provider playlists sharing the News group
+    fs::write(
+        providers.join("a.m3u"),
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",A News\nhttp://a.example.com/news\n",
+    )
+    .unwrap();
+    fs::write(
+        providers.join("b.m3u"),
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",B News\nhttp://b.example.com/news\n\
+         #EXTINF:-1 group-title=\"Sports\",B Sports\nhttp://b.example.com/sports\n",
+    )
+    .unwrap();
+    let extra = temp_dir.path().join("extra.m3u");
+    fs::write(
+        &extra,
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",C News\nhttp://c.example.com/news\n",
+    )
+    .unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(providers.join("*.m3u"))
+        .arg("--input")
+        .arg(&extra)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--tag-source")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Parsing 3 M3U files:"));
+
+    // News from all three sources ends up in one file, in input order
+    let news = fs::read_to_string(output_dir.join("News.m3u")).unwrap();
+    let a = news.find("http://a.example.com/news").unwrap();
+    let b = news.find("http://b.example.com/news").unwrap();
+    let c = news.find("http://c.example.com/news").unwrap();
+    assert!(a < b && b < c);
+    assert!(news.contains(r#"#EXTINF:-1 group-title="News" source="extra",C News"#));
+    assert!(output_dir.join("Sports.m3u").exists());
+}
+
+#[test]
+fn test_input_glob_without_matches_fails() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .arg(temp_dir.path().join("*.m3u"))
+        .arg("--output")
+        .arg(temp_dir.path().join("output"))
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no input files match"),
+        "stderr: {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn test_stdin_extract_to_stdout() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let m3u_content = r#"#EXTM3U
+#EXTINF:-1 group-title="Sports",Sports 1
+http://example.com/sports1.m3u8
+#EXTINF:-1 group-title="News",News 1
+http://example.com/news1.m3u8
+#EXTINF:-1 group-title="Sports HD",Sports 2
+#EXTVLCOPT:http-user-agent=VLC
+http://example.com/sports2.m3u8
+"#;
+
+    let binary = get_binary_path();
+    let mut child = Command::new(binary)
+        .arg("--input")
+        .arg("-")
+        .arg("--group")
+        .arg("Sports*")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(m3u_content.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Only the playlist goes to stdout
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "#EXTM3U\n\
+         #EXTINF:-1 group-title=\"Sports\",Sports 1\n\
+         http://example.com/sports1.m3u8\n\
+         #EXTINF:-1 group-title=\"Sports HD\",Sports 2\n\
+         #EXTVLCOPT:http-user-agent=VLC\n\
+         http://example.com/sports2.m3u8\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Extracted 2 channels"));
+}
+
+#[test]
+fn test_stdin_split_into_directory() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let temp_dir = TempDir::new().unwrap();
+    let output_dir = temp_dir.path().join("output");
+
+    let binary = get_binary_path();
+    let mut child = Command::new(binary)
+        .arg("--input")
+        .arg("-")
+        .arg("--output")
+        .arg(&output_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"#EXTM3U\n#EXTINF:-1 group-title=\"News\",News\nhttp://example.com/news\n")
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let news = fs::read_to_string(output_dir.join("News.m3u")).unwrap();
+    assert!(news.contains("http://example.com/news"));
+}
+
+#[test]
+fn test_compressed_inputs_and_outputs() {
+    use std::io::{Read, Write};
+
+    let temp_dir = TempDir::new().unwrap();
+    let output_dir = temp_dir.path().join("output");
+    let playlist = |provider: &str| {
+        format!(
+            "#EXTM3U\n#EXTINF:-1 group-title=\"News\",{} News\nhttp://{}.example.com/news\n",
+            provider, provider
+        )
+    };
+
+    // Create one input per supported compression
+    let gzip_path = temp_dir.path().join("a.m3u.gz");
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(playlist("a").as_bytes()).unwrap();
+    fs::write(&gzip_path, gzip.finish().unwrap()).unwrap();
+
+    let xz_path = temp_dir.path().join("b.m3u.xz");
+    let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+    xz.write_all(playlist("b").as_bytes()).unwrap();
+    fs::write(&xz_path, xz.finish().unwrap()).unwrap();
+
+    let zstd_path = temp_dir.path().join("c.m3u.zst");
+    fs::write(
+        &zstd_path,
+        zstd::encode_all(playlist("c").as_bytes(), 0).unwrap(),
+    )
+    .unwrap();
+
+    let zip_path = temp_dir.path().join("bundle.zip");
+    let mut archive = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+    archive
+        .start_file("d.m3u8", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    archive.write_all(playlist("d").as_bytes()).unwrap();
+    archive.finish().unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(binary)
+        .arg("--input")
+        .args([&gzip_path, &xz_path, &zstd_path, &zip_path])
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--tag-source")
+        .arg("--compress")
+        .arg("gz")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Parsing 4 M3U files:"));
+    assert!(stdout.contains("bundle.zip!d.m3u8"));
+    assert!(stdout.contains("Created: News.m3u.gz (4 channels)"));
+    assert!(!output_dir.join("News.m3u").exists());
+
+    let mut news = String::new();
+    flate2::read::GzDecoder::new(fs::File::open(output_dir.join("News.m3u.gz")).unwrap())
+        .read_to_string(&mut news)
+        .unwrap();
+    for provider in ["a", "b", "c", "d"] {
+        assert!(news.contains(&format!(
+            "#EXTINF:-1 group-title=\"News\" source=\"{}\",{} News",
+            provider, provider
+        )));
+    }
+}
+
+#[test]
+fn test_input_encodings() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // A Windows-1252 playlist without a byte order mark whose first
+    // non-ASCII byte comes after the first 64 KiB, so auto-detection takes
+    // it for UTF-8 until then
+    let mut content = b"#EXTM3U\n".to_vec();
+    for i in 0..2000 {
+        content.extend_from_slice(
+            format!(
+                "#EXTINF:-1 group-title=\"News\",News {}\nhttp://example.com/{}\n",
+                i, i
+            )
+            .as_bytes(),
+        );
+    }
+    content.extend_from_slice(
+        b"#EXTINF:-1 group-title=\"Caf\xe9\",Caf\xe9 TV\nhttp://example.com/cafe\n",
+    );
+    fs::write(&input_file, &content).unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(get_binary_path())
+            .arg("--input")
+            .arg(&input_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["--filenames", "unicode"]);
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Output is UTF-8 without a byte order mark
+    let cafe = fs::read_to_string(output_dir.join("Café.m3u")).unwrap();
+    assert!(cafe.starts_with("#EXTM3U\n#EXTINF:-1 group-title=\"Café\",Café TV\n"));
+    fs::remove_dir_all(&output_dir).unwrap();
+
+    let output = run(&["--encoding", "windows-1252", "--filenames", "unicode"]);
+    assert!(output.status.success());
+    assert!(output_dir.join("Café.m3u").exists());
+    fs::remove_dir_all(&output_dir).unwrap();
+
+    // A UTF-8 byte order mark rules out Windows-1252, and takes precedence
+    // over --encoding
+    let mut with_bom = b"\xef\xbb\xbf".to_vec();
+    with_bom.extend_from_slice(&content);
+    fs::write(&input_file, &with_bom).unwrap();
+    let output = run(&["--encoding", "windows-1252"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("input.m3u: input is not valid UTF-8"),
+        "{}",
+        stderr
+    );
+
+    let output = run(&["--lossy"]);
+    assert!(output.status.success());
+    assert!(output_dir.join("Caf.m3u").exists());
+
+    let output = run(&["--encoding", "klingon"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_parse_diagnostics_and_strict() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create a playlist with a channel without URL and a stray line
+    let content = r#"#EXTM3U
+#EXTINF:-1 group-title="News",No URL
+#EXTINF:-1 group-title="News",News 1
+http://example.com/news1
+garbage
+"#;
+    fs::write(&input_file, content).unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Found 2 problem(s) in the input (1 error(s), 1 warning(s)):"));
+    assert!(stderr.contains("input.m3u:2: error: #EXTINF has no URL"));
+    assert!(stderr.contains("input.m3u:5: warning: line \"garbage\" does not follow an #EXTINF"));
+    assert!(output_dir.join("News.m3u").exists());
+
+    // --strict fails before writing anything, also when extracting
+    fs::remove_dir_all(&output_dir).unwrap();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--strict")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--strict"));
+    assert!(!output_dir.exists());
+
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--extract")
+        .arg("News")
+        .arg("--strict")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_header_attributes_are_propagated() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create a playlist with an EPG link in its header
+    let content = r#"#EXTM3U url-tvg="http://example.com/epg.xml" tvg-shift="1" x-provider="acme"
+#EXTINF:-1 group-title="News",News 1
+http://example.com/news1
+#EXTINF:-1 group-title="Sports",Sports 1
+http://example.com/sports1
+"#;
+    fs::write(&input_file, content).unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    for file in ["News.m3u", "Sports.m3u"] {
+        let written = fs::read_to_string(output_dir.join(file)).unwrap();
+        assert!(written.starts_with(
+            "#EXTM3U url-tvg=\"http://example.com/epg.xml\" tvg-shift=\"1\" x-provider=\"acme\"\n"
+        ));
+    }
+
+    // Override one attribute and strip others
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--extract")
+        .arg("News")
+        .arg("--header")
+        .arg("url-tvg=http://example.com/other.xml")
+        .arg("--header")
+        .arg("x-tvg-url=http://example.com/epg2.xml")
+        .arg("--strip-header")
+        .arg("x-*")
+        .arg("--strip-header")
+        .arg("TVG-SHIFT")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with(
+        "#EXTM3U url-tvg=\"http://example.com/other.xml\" x-tvg-url=\"http://example.com/epg2.xml\"\n"
+    ));
+
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--extract")
+        .arg("News")
+        .arg("--header")
+        .arg("no value")
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_lossless_keeps_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // Create a playlist with comments and unknown directives between channels
+    let content = "#EXTM3U\r\n\
+                   #EXTINF:-1 group-title=\"News\",News 1\r\n\
+                   http://example.com/news1\r\n\
+                   \r\n\
+                   # Sports start here\r\n\
+                   #EXTGRP:Sports\r\n\
+                   #EXTINF:-1 group-title=\"Sports\",Sports 1\r\n\
+                   http://example.com/sports1\r\n";
+    fs::write(&input_file, content).unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--lossless")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let sports = fs::read_to_string(output_dir.join("Sports.m3u")).unwrap();
+    assert_eq!(
+        sports,
+        "#EXTM3U\n\
+         \r\n\
+         # Sports start here\r\n\
+         #EXTGRP:Sports\r\n\
+         #EXTINF:-1 group-title=\"Sports\",Sports 1\r\n\
+         http://example.com/sports1\r\n"
+    );
+
+    // Without --lossless, only the channel and its #EXTGRP line are written
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let sports = fs::read_to_string(output_dir.join("Sports.m3u")).unwrap();
+    assert!(!sports.contains("# Sports start here"));
+    assert!(sports.contains("#EXTGRP:Sports\n#EXTINF"));
+}
+
+#[test]
+fn test_extgrp_groups() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // One channel grouped by #EXTGRP only, one by both forms
+    let content = r#"#EXTM3U
+#EXTINF:-1,Movie 1
+#EXTGRP:Movies
+http://example.com/movie1
+#EXTGRP:Films
+#EXTINF:-1 group-title="News",News 1
+http://example.com/news1
+"#;
+    fs::write(&input_file, content).unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let movies = fs::read_to_string(output_dir.join("Movies.m3u")).unwrap();
+    assert!(movies.contains("#EXTINF:-1,Movie 1\n#EXTGRP:Movies\nhttp://example.com/movie1"));
+    assert!(output_dir.join("News.m3u").exists());
+    assert!(!output_dir.join("Unknown.m3u").exists());
+
+    // Prefer #EXTGRP: the group-title it overrides is rewritten to match
+    let output_dir = temp_dir.path().join("prefer");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--group-source")
+        .arg("extgrp")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let films = fs::read_to_string(output_dir.join("Films.m3u")).unwrap();
+    assert_eq!(
+        films,
+        "#EXTM3U\n\
+         #EXTGRP:Films\n\
+         #EXTINF:-1 group-title=\"Films\",News 1\n\
+         http://example.com/news1\n"
+    );
+
+    // Prefer #EXTGRP and write the group as a group-title attribute only
+    let output_dir = temp_dir.path().join("extgrp");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--group-source")
+        .arg("extgrp")
+        .arg("--group-output")
+        .arg("group-title")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    assert!(!output_dir.join("News.m3u").exists());
+    let films = fs::read_to_string(output_dir.join("Films.m3u")).unwrap();
+    assert_eq!(
+        films,
+        "#EXTM3U\n\
+         #EXTINF:-1 group-title=\"Films\",News 1\n\
+         http://example.com/news1\n"
+    );
+
+    // Write the group as an #EXTGRP line only
+    let output_dir = temp_dir.path().join("lines");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--group-output")
+        .arg("extgrp")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let news = fs::read_to_string(output_dir.join("News.m3u")).unwrap();
+    assert_eq!(
+        news,
+        "#EXTM3U\n\
+         #EXTINF:-1,News 1\n\
+         #EXTGRP:News\n\
+         http://example.com/news1\n"
+    );
+    // --lossless groups the first channel by its #EXTGRP line as well
+    let content = "#EXTM3U\n\
+                   # c\n\
+                   #EXTGRP:Movies\n\
+                   #EXTINF:-1,Film A\n\
+                   http://a\n\
+                   #EXTGRP:Movies\n\
+                   #EXTINF:-1,Film B\n\
+                   http://b\n";
+    fs::write(&input_file, content).unwrap();
+    let output_dir = temp_dir.path().join("lossless");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--lossless")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    assert!(!output_dir.join("Unknown.m3u").exists());
+    let movies = fs::read_to_string(output_dir.join("Movies.m3u")).unwrap();
+    assert_eq!(movies, content);
+}
+
+#[test]
+fn test_plain_playlists() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("music.m3u");
+    let output_dir = temp_dir.path().join("output");
+
+    // A plain playlist: one path or URL per line, without #EXTINF
+    let content = "# Favourites\n\
+                   Music/Rock/song1.mp3\n\
+                   Music/Jazz/song2.flac\n\
+                   Music/Rock/song3.mp3\n\
+                   http://radio.example.com/stream.mp3\n";
+    fs::write(&input_file, content).unwrap();
+
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Unknown: 4 channels"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("problem"));
+
+    // Grouped by parent directory, the entries are written as they were
+    let output_dir = temp_dir.path().join("directory");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--plain-group")
+        .arg("directory")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let rock = fs::read_to_string(output_dir.join("Rock.m3u")).unwrap();
+    assert_eq!(
+        rock,
+        "#EXTM3U\nMusic/Rock/song1.mp3\nMusic/Rock/song3.mp3\n"
+    );
+    assert!(output_dir.join("Jazz.m3u").exists());
+    assert!(output_dir.join("Unknown.m3u").exists());
+
+    // Grouped by file extension
+    let output_dir = temp_dir.path().join("extension");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--plain-group")
+        .arg("extension")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let mp3 = fs::read_to_string(output_dir.join("mp3.m3u")).unwrap();
+    assert_eq!(mp3.lines().count(), 4);
+    assert!(output_dir.join("flac.m3u").exists());
+
+    // After an #EXTM3U header, a bare line is no plain entry but a problem
+    fs::write(
+        &input_file,
+        "#EXTM3U\n<html>garbage\n#EXTINF:-1 group-title=\"News\",BBC\nhttp://example.com/bbc\n",
+    )
+    .unwrap();
+    let output_dir = temp_dir.path().join("strict");
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&input_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--strict")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Unknown"));
+    assert!(!output_dir.exists());
+}
+
+#[test]
+fn test_hls_playlists() {
+    let temp_dir = TempDir::new().unwrap();
+    let master_file = temp_dir.path().join("master.m3u8");
+    let media_file = temp_dir.path().join("video.m3u8");
+    let output_dir = temp_dir.path().join("output");
+
+    let master = r#"#EXTM3U
+#EXT-X-VERSION:4
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="low",NAME="English",URI="audio-low.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="high",NAME="English",URI="audio-high.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,AUDIO="low"
+360p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,AUDIO="high"
+1080p.m3u8
+"#;
+    fs::write(&master_file, master).unwrap();
+
+    // A master playlist is split by variant resolution, not into channels
+    let binary = get_binary_path();
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&master_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Found 2 variant groups by RESOLUTION:"));
+    // Relative URIs still point next to the input
+    let full_hd = fs::read_to_string(output_dir.join("1920x1080.m3u8")).unwrap();
+    assert_eq!(
+        full_hd,
+        "#EXTM3U\n\
+         #EXT-X-VERSION:4\n\
+         #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"high\",NAME=\"English\",URI=\"../audio-high.m3u8\"\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,AUDIO=\"high\"\n\
+         ../1080p.m3u8\n"
+    );
+    assert!(output_dir.join("640x360.m3u8").exists());
+    assert!(!output_dir.join("Unknown.m3u").exists());
+
+    // Written next to the input, they are kept as they are
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&master_file)
+        .arg("--output")
+        .arg(temp_dir.path())
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let full_hd = fs::read_to_string(temp_dir.path().join("1920x1080.m3u8")).unwrap();
+    assert!(full_hd.ends_with("\n1080p.m3u8\n"));
+
+    let mut media = String::from(
+        "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-MEDIA-SEQUENCE:7\n#EXT-X-PLAYLIST-TYPE:VOD\n",
+    );
+    for i in 0..5 {
+        media.push_str(&format!("#EXTINF:10.0,\nsegment{}.ts\n", i));
+    }
+    media.push_str("#EXT-X-ENDLIST\n");
+    fs::write(&media_file, &media).unwrap();
+
+    // A media playlist is rejected without --hls-chunk
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&media_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is an HLS media playlist of 5 segments"));
+    assert!(stderr.contains("--hls-chunk"));
+
+    // With --hls-chunk it is cut into chunks that count on
+    let output = Command::new(&binary)
+        .arg("--input")
+        .arg(&media_file)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--hls-chunk")
+        .arg("20")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    for name in ["video-001.m3u8", "video-002.m3u8", "video-003.m3u8"] {
+        assert!(output_dir.join(name).exists(), "{} is missing", name);
+    }
+    let second = fs::read_to_string(output_dir.join("video-002.m3u8")).unwrap();
+    assert_eq!(
+        second,
+        "#EXTM3U\n\
+         #EXT-X-TARGETDURATION:10\n\
+         #EXT-X-PLAYLIST-TYPE:VOD\n\
+         #EXT-X-MEDIA-SEQUENCE:9\n\
+         #EXTINF:10.0,\n\
+         ../segment2.ts\n\
+         #EXTINF:10.0,\n\
+         ../segment3.ts\n\
+         #EXT-X-ENDLIST\n"
+    );
+    // Options about channels are rejected rather than ignored
+    for extra in [
+        &["--where", "title~News"][..],
+        &["--dedupe", "url"],
+        &["--name-template", "{group}.m3u8"],
+        &["--sort", "name"],
+        &["--header", "url-tvg=http://example.com/epg.xml"],
+        &["--strict"],
+        &["--aggregate"],
+        &["--split-values", ";"],
+        &["--lossless"],
+        &["--group-source", "extgrp"],
+        &["--group-output", "both"],
+        &["--plain-group", "host"],
+        &["--hls-chunk", "10"],
+    ] {
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(&master_file)
+            .arg("--output")
+            .arg(&output_dir)
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!(
+                "{} does not apply to HLS master playlists",
+                extra[0]
+            )),
+            "{}",
+            stderr
+        );
+    }
+    // --variant-by is for master playlists only, --hls-chunk for media
+    // playlists only
+    let playlist_file = temp_dir.path().join("channels.m3u");
+    fs::write(
+        &playlist_file,
+        "#EXTM3U\n#EXTINF:-1 group-title=\"News\",BBC\nhttp://example.com/bbc\n",
+    )
+    .unwrap();
+    for (input, extra, message) in [
+        (
+            &media_file,
+            ["--variant-by", "CODECS"],
+            "--variant-by does not apply to HLS media playlists",
+        ),
+        (
+            &playlist_file,
+            ["--variant-by", "CODECS"],
+            "--variant-by only applies to HLS master playlists",
+        ),
+        (
+            &playlist_file,
+            ["--hls-chunk", "10"],
+            "--hls-chunk only applies to HLS media playlists",
+        ),
+    ] {
+        let output = Command::new(&binary)
+            .arg("--input")
+            .arg(input)
+            .arg("--output")
+            .arg(temp_dir.path().join("rejected"))
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message));
+    }
+    assert!(!temp_dir.path().join("rejected").exists());
+}
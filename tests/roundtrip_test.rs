@@ -0,0 +1,165 @@
+use m3u_splitter::{parse_lossless, parse_str, write_lossless};
+use proptest::prelude::*;
+
+/// Lines an IPTV playlist is made of, including ones the parser does not
+/// understand.
+fn playlist_line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("#EXTM3U".to_string()),
+        Just(r#"#EXTM3U url-tvg="http://example.com/epg.xml""#.to_string()),
+        "[A-Za-z ]{0,12}".prop_map(|title| format!("#EXTINF:-1 group-title=\"News\",{}", title)),
+        "[a-z]{0,6}".prop_map(|group| format!("#EXTINF:-1 tvg-id=x group-title='{}',T", group)),
+        Just(r#"#EXTINF:-1 group-title="Broken,Title"#.to_string()),
+        Just("#EXTINF:abc,Bad duration".to_string()),
+        "[a-z]{1,8}".prop_map(|path| format!("http://example.com/{}", path)),
+        Just("#EXTVLCOPT:http-user-agent=VLC".to_string()),
+        Just("#EXTGRP:Sports".to_string()),
+        Just("#PLAYLIST:My channels".to_string()),
+        "# [a-z ]{0,10}".prop_map(|comment| comment),
+        "[ \t]{0,3}".prop_map(|blank| blank),
+        "\\PC{0,10}".prop_map(|text| text),
+        Just("\u{feff}#EXTM3U".to_string()),
+    ]
+}
+
+fn playlist() -> impl Strategy<Value = String> {
+    (
+        prop::collection::vec(
+            (
+                playlist_line(),
+                prop_oneof![Just("\n"), Just("\r\n"), Just(" \n")],
+            ),
+            0..30,
+        ),
+        any::<bool>(),
+    )
+        .prop_map(|(lines, final_newline)| {
+            let mut text: String = lines
+                .into_iter()
+                .map(|(line, ending)| line + ending)
+                .collect();
+            if !final_newline && text.ends_with('\n') {
+                text.pop();
+            }
+            text
+        })
+}
+
+fn round_trip(text: &str) -> String {
+    let playlist = parse_lossless(text.as_bytes()).unwrap();
+    let mut written = Vec::new();
+    write_lossless(&mut written, &playlist).unwrap();
+    String::from_utf8(written).unwrap()
+}
+
+proptest! {
+    #[test]
+    fn lossless_round_trip_is_byte_identical(text in playlist()) {
+        prop_assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn lossless_round_trip_of_any_text(text in any::<String>()) {
+        prop_assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn lossless_parsing_finds_the_same_entries(text in playlist()) {
+        let lossless = parse_lossless(text.as_bytes()).unwrap();
+        let plain = parse_str(&text);
+        // Only lossless parsing keeps other comments than #EXTGRP lines
+        let summary = |entries: &[m3u_splitter::Entry]| {
+            entries
+                .iter()
+                .map(|e| {
+                    let groups: Vec<String> = e
+                        .comments
+                        .iter()
+                        .filter(|line| line.starts_with("#EXTGRP"))
+                        .cloned()
+                        .collect();
+                    (
+                        e.extinf_line.clone(),
+                        e.url.clone(),
+                        e.directives.clone(),
+                        groups,
+                        e.extgrp().map(str::to_string),
+                        e.group_name.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        prop_assert_eq!(summary(&lossless.entries), summary(&plain.entries));
+        prop_assert_eq!(lossless.header, plain.header);
+    }
+}
+
+#[test]
+fn lossless_entries_keep_their_comments() {
+    let text = "#EXTM3U\r\n\
+                #PLAYLIST:Mine\r\n\
+                #EXTINF:-1 group-title=\"News\",News 1\r\n\
+                http://example.com/news1\r\n\
+                \r\n\
+                # Sports channels\r\n\
+                #EXTGRP:Sports\r\n\
+                #EXTINF:-1 group-title=\"Sports\",Sports 1\r\n\
+                #EXTVLCOPT:http-user-agent=VLC\r\n\
+                http://example.com/sports1  \r\n\
+                # end\r\n";
+    let mut playlist = parse_lossless(text.as_bytes()).unwrap();
+
+    assert_eq!(playlist.preamble, vec!["#EXTM3U\r\n", "#PLAYLIST:Mine\r\n"]);
+    assert_eq!(playlist.trailer, vec!["# end\r\n"]);
+    assert!(playlist.entries[0].comments.is_empty());
+    assert_eq!(
+        playlist.entries[1].comments,
+        vec!["# Sports channels", "#EXTGRP:Sports"]
+    );
+    assert_eq!(playlist.entries[1].url, "http://example.com/sports1");
+
+    // A changed EXTINF line is written in place, keeping its line ending
+    playlist.entries[1].set_attribute("group-title", "Sport");
+    let mut written = Vec::new();
+    write_lossless(&mut written, &playlist).unwrap();
+    assert_eq!(
+        String::from_utf8(written).unwrap(),
+        text.replace("group-title=\"Sports\"", "group-title=\"Sport\"")
+    );
+
+    // Other changes fall back to writing the entry's parsed lines
+    playlist.entries[1].url = "http://example.com/other".to_string();
+    let mut written = Vec::new();
+    write_lossless(&mut written, &playlist).unwrap();
+    assert!(String::from_utf8(written).unwrap().ends_with(
+        "# Sports channels\n\
+         #EXTGRP:Sports\n\
+         #EXTINF:-1 group-title=\"Sport\",Sports 1\n\
+         #EXTVLCOPT:http-user-agent=VLC\n\
+         http://example.com/other\n\
+         # end\r\n"
+    ));
+}
+
+#[test]
+fn lossless_first_entry_keeps_its_comments() {
+    let text = "\u{feff}#EXTM3U\n\
+                #PLAYLIST:Mine\n\
+                \n\
+                # Movies\n\
+                #EXTGRP:Movies\n\
+                #EXTINF:-1,Film A\n\
+                http://example.com/a\n";
+    let playlist = parse_lossless(text.as_bytes()).unwrap();
+
+    assert_eq!(
+        playlist.preamble,
+        vec!["\u{feff}#EXTM3U\n", "#PLAYLIST:Mine\n", "\n"]
+    );
+    assert_eq!(
+        playlist.entries[0].comments,
+        vec!["# Movies", "#EXTGRP:Movies"]
+    );
+    assert_eq!(playlist.entries[0].extgrp(), Some("Movies"));
+    assert_eq!(round_trip(text), text);
+}